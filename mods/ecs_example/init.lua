@@ -55,3 +55,25 @@ function on_update(delta_time)
         proxy:set_camera_eye(camera_entity_id, camera_position)
     end
 end
+
+-- Systems run every frame, after on_update
+local elapsed = 0.0
+local next_report = 5.0
+
+systems = {
+    track_time = {
+        reads = { "DeltaTime" },
+        run = function(data)
+            elapsed = elapsed + data:read("DeltaTime").seconds
+        end,
+    },
+    report_time = {
+        after = { "track_time" },
+        run = function(data)
+            if elapsed >= next_report then
+                print("Lua: " .. tostring(math.floor(elapsed)) .. " seconds elapsed")
+                next_report = next_report + 5.0
+            end
+        end,
+    },
+}
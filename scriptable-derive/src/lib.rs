@@ -0,0 +1,141 @@
+//! Derive macro for the `scriptable::Reflection` trait
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Field, Fields};
+
+/// Implements `Reflection` for a struct with named fields.
+///
+/// Every field is exposed to scripts under its own name, and must
+/// implement `Clone`, `rlua::ToLua` and `rlua::FromLua`. Fields marked
+/// `#[reflection(skip)]` are left out.
+///
+/// ```ignore
+/// #[derive(Clone, Reflection)]
+/// pub struct Score {
+///     pub value: i32,
+///     #[reflection(skip)]
+///     history: Vec<i32>,
+/// }
+/// ```
+#[proc_macro_derive(Reflection, attributes(reflection))]
+pub fn derive_reflection(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match impl_reflection(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn impl_reflection(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    name,
+                    "Reflection can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                name,
+                "Reflection can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut exposed = Vec::new();
+    for field in fields {
+        if !is_skipped(field)? {
+            exposed.push(field);
+        }
+    }
+
+    let idents: Vec<_> = exposed.iter().map(|field| &field.ident).collect();
+    let names: Vec<_> = idents
+        .iter()
+        .map(|ident| ident.as_ref().unwrap().to_string())
+        .collect();
+    let types: Vec<_> = exposed.iter().map(|field| &field.ty).collect();
+    let type_names: Vec<_> = types
+        .iter()
+        .map(|ty| quote!(#ty).to_string().replace(' ', ""))
+        .collect();
+    let struct_name = name.to_string();
+
+    Ok(quote! {
+        impl #impl_generics crate::scriptable::Reflection for #name #ty_generics #where_clause {
+            fn fields(&self) -> &'static [crate::scriptable::FieldInfo] {
+                &[
+                    #(crate::scriptable::FieldInfo {
+                        name: #names,
+                        type_name: #type_names,
+                    },)*
+                ]
+            }
+
+            fn get_field<'lua>(
+                &self,
+                lua_ctx: ::rlua::Context<'lua>,
+                name: &str,
+            ) -> ::rlua::Result<::rlua::Value<'lua>> {
+                match name {
+                    #(#names => ::rlua::ToLua::to_lua(
+                        ::std::clone::Clone::clone(&self.#idents),
+                        lua_ctx,
+                    ),)*
+                    _ => Ok(::rlua::Value::Nil),
+                }
+            }
+
+            fn set_field<'lua>(
+                &mut self,
+                lua_ctx: ::rlua::Context<'lua>,
+                name: &str,
+                value: ::rlua::Value<'lua>,
+            ) -> ::rlua::Result<()> {
+                match name {
+                    #(#names => {
+                        self.#idents = <#types as ::rlua::FromLua>::from_lua(value, lua_ctx)?;
+                        Ok(())
+                    })*
+                    _ => Err(::rlua::Error::RuntimeError(format!(
+                        "{} has no field '{}'",
+                        #struct_name, name
+                    ))),
+                }
+            }
+        }
+    })
+}
+
+/// Whether a field is marked `#[reflection(skip)]`.
+fn is_skipped(field: &Field) -> syn::Result<bool> {
+    let mut skipped = false;
+
+    for attr in &field.attrs {
+        if !attr.path().is_ident("reflection") {
+            continue;
+        }
+
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("skip") {
+                skipped = true;
+                Ok(())
+            } else {
+                Err(meta.error("unknown reflection attribute, expected `skip`"))
+            }
+        })?;
+    }
+
+    Ok(skipped)
+}
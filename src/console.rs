@@ -0,0 +1,167 @@
+//! Commands typed into the terminal while the application runs
+
+use crate::modding::{ModHub, SettingValue};
+use crossbeam::channel::{unbounded, Receiver};
+use rlua::Context;
+use std::{
+    fmt,
+    io::{self, BufRead},
+    thread,
+};
+
+/// A command for managing mods.
+///
+/// ```text
+/// mods
+/// enable <mod>
+/// disable <mod>
+/// resume <mod>
+/// set <mod> <setting> <value>
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Lists the mods, with the problems found with each.
+    Mods,
+    Enable(String),
+    Disable(String),
+    /// Resumes a mod that was suspended.
+    Resume(String),
+    /// Changes a setting of a mod, and saves the user settings.
+    Set {
+        mod_name: String,
+        setting: String,
+        value: SettingValue,
+    },
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["mods"] => Ok(Command::Mods),
+            ["enable", mod_name] => Ok(Command::Enable((*mod_name).to_owned())),
+            ["disable", mod_name] => Ok(Command::Disable((*mod_name).to_owned())),
+            ["resume", mod_name] => Ok(Command::Resume((*mod_name).to_owned())),
+            ["set", mod_name, setting, value @ ..] if !value.is_empty() => Ok(Command::Set {
+                mod_name: (*mod_name).to_owned(),
+                setting: (*setting).to_owned(),
+                value: SettingValue::parse(&value.join(" ")),
+            }),
+            _ => Err(format!("unknown command '{}'", line.trim())),
+        }
+    }
+
+    /// Applies the command to the mods, printing the outcome.
+    ///
+    /// Invalid settings are reported rather than returned.
+    pub fn run(&self, lua_ctx: Context, mod_hub: &mut ModHub) -> rlua::Result<()> {
+        let (mod_name, done) = match self {
+            Command::Mods => {
+                list_mods(mod_hub);
+                return Ok(());
+            }
+            Command::Enable(mod_name) => (mod_name, mod_hub.enable_mod(lua_ctx, mod_name)?),
+            Command::Disable(mod_name) => (mod_name, mod_hub.disable_mod(lua_ctx, mod_name)?),
+            Command::Resume(mod_name) => (mod_name, mod_hub.resume_mod(lua_ctx, mod_name)?),
+            Command::Set {
+                mod_name,
+                setting,
+                value,
+            } => {
+                let result = mod_hub.set_setting(lua_ctx, mod_name, setting, value.clone());
+                match result {
+                    Ok(()) => {
+                        println!("{}", self);
+                        if let Err(err) = mod_hub.save_user_settings() {
+                            eprintln!("failed saving mod settings: {}", err);
+                        }
+                    }
+                    Err(err) => eprintln!("{}", err),
+                }
+                return Ok(());
+            }
+        };
+
+        match (self, done) {
+            (_, true) => println!("{}", self),
+            (Command::Resume(_), false) => println!("Mod '{}' is not suspended", mod_name),
+            (_, false) => println!("Mod '{}' not found", mod_name),
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Command::Mods => write!(f, "Listed mods"),
+            Command::Enable(mod_name) => write!(f, "Enabled mod '{}'", mod_name),
+            Command::Disable(mod_name) => write!(f, "Disabled mod '{}'", mod_name),
+            Command::Resume(mod_name) => write!(f, "Resumed mod '{}'", mod_name),
+            Command::Set {
+                mod_name,
+                setting,
+                value,
+            } => write!(f, "Set '{}' of mod '{}' to {}", setting, mod_name, value),
+        }
+    }
+}
+
+fn list_mods(mod_hub: &ModHub) {
+    for m in mod_hub.mods() {
+        let meta = m.meta();
+        println!(
+            "{} {} ({:?}, priority {}) in '{}', fingerprint {}",
+            m.name(),
+            meta.version,
+            m.state(),
+            m.priority(),
+            m.path().to_string_lossy(),
+            m.fingerprint()
+        );
+        if !meta.description.is_empty() {
+            println!("    {}", meta.description);
+        }
+        if !meta.authors.is_empty() {
+            println!("    by {}", meta.authors.join(", "));
+        }
+        for (setting, value) in m.settings() {
+            println!("    {} = {}", setting, value);
+        }
+        for err in mod_hub.report().errors_for(m.name()) {
+            println!("    problem: {}", err);
+        }
+    }
+}
+
+/// Reads commands from standard input on a separate thread, until the
+/// input is closed.
+///
+/// Lines that aren't commands are reported and skipped.
+pub fn spawn() -> Receiver<Command> {
+    let (sender, receiver) = unbounded();
+
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match Command::parse(&line) {
+                Ok(command) => {
+                    if sender.send(command).is_err() {
+                        break;
+                    }
+                }
+                Err(err) => eprintln!("{}", err),
+            }
+        }
+    });
+
+    receiver
+}
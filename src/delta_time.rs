@@ -1,7 +1,44 @@
+use crate::scriptable::{FieldInfo, Reflection};
+use rlua::{Context, FromLua, ToLua, Value};
 use std::time::Duration;
 
 pub struct DeltaTime(Duration);
 
+impl Reflection for DeltaTime {
+    fn fields(&self) -> &'static [FieldInfo] {
+        &[FieldInfo {
+            name: "seconds",
+            type_name: "f32",
+        }]
+    }
+
+    fn get_field<'lua>(&self, lua_ctx: Context<'lua>, name: &str) -> rlua::Result<Value<'lua>> {
+        match name {
+            "seconds" => self.as_secs().to_lua(lua_ctx),
+            _ => Ok(Value::Nil),
+        }
+    }
+
+    fn set_field<'lua>(
+        &mut self,
+        lua_ctx: Context<'lua>,
+        name: &str,
+        value: Value<'lua>,
+    ) -> rlua::Result<()> {
+        match name {
+            "seconds" => {
+                let seconds = f32::from_lua(value, lua_ctx)?;
+                self.0 = Duration::from_secs_f32(seconds.max(0.0));
+                Ok(())
+            }
+            _ => Err(rlua::Error::RuntimeError(format!(
+                "DeltaTime has no field '{}'",
+                name
+            ))),
+        }
+    }
+}
+
 impl DeltaTime {
     pub fn new(duration: Duration) -> Self {
         DeltaTime(duration)
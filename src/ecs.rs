@@ -1,6 +1,10 @@
 //! Interface between lua and specs
 
-use crate::{camera, colors, delta_time, input, linear, shape};
+use crate::{
+    camera, colors, delta_time, input, linear,
+    script_components::{ComponentRegistry, ScriptComponents, ScriptFields},
+    shape,
+};
 use rlua::{MetaMethod, UserData, UserDataMethods};
 use specs::prelude::*;
 use std::marker::PhantomData;
@@ -76,6 +80,80 @@ where
             },
         );
 
+        methods.add_method_mut(
+            "add_component",
+            |_, proxy, (entity_id, name, fields): (EntityId, String, Option<ScriptFields>)| {
+                let component = proxy
+                    .data
+                    .component_registry
+                    .instantiate(&name, fields.unwrap_or_default())
+                    .map_err(rlua::Error::RuntimeError)?;
+
+                let entity: specs::Entity = entity_id.into();
+                let components = proxy
+                    .data
+                    .script_components
+                    .entry(entity)
+                    .map_err(|_| {
+                        rlua::Error::RuntimeError(format!("entity {:?} is not alive", entity))
+                    })?
+                    .or_insert_with(ScriptComponents::new);
+                components.insert(name, component);
+                Ok(())
+            },
+        );
+
+        // Copy of the fields, changes are applied with `set_component`
+        methods.add_method(
+            "get_component",
+            |_, proxy, (entity_id, name): (EntityId, String)| {
+                Ok(proxy
+                    .data
+                    .script_components
+                    .get(entity_id.into())
+                    .and_then(|components| components.get(&name))
+                    .cloned())
+            },
+        );
+
+        methods.add_method_mut(
+            "set_component",
+            |_, proxy, (entity_id, name, fields): (EntityId, String, ScriptFields)| {
+                let component = proxy
+                    .data
+                    .script_components
+                    .get_mut(entity_id.into())
+                    .and_then(|components| components.get_mut(&name))
+                    .ok_or_else(|| {
+                        rlua::Error::RuntimeError(format!(
+                            "entity {:?} has no component '{}'",
+                            entity_id.0, name
+                        ))
+                    })?;
+                proxy
+                    .data
+                    .component_registry
+                    .update(&name, component, fields)
+                    .map_err(rlua::Error::RuntimeError)
+            },
+        );
+
+        methods.add_method_mut(
+            "remove_component",
+            |_, proxy, (entity_id, name): (EntityId, String)| {
+                let entity: specs::Entity = entity_id.into();
+                let components = match proxy.data.script_components.get_mut(entity) {
+                    Some(components) => components,
+                    None => return Ok(false),
+                };
+                let removed = components.remove(&name).is_some();
+                if components.is_empty() {
+                    proxy.data.script_components.remove(entity);
+                }
+                Ok(removed)
+            },
+        );
+
         methods.add_method("is_key_pressed", |_, proxy, key: u32| {
             let is_pressed = input::virtual_key_code_from_int(key)
                 .and_then(|code| proxy.data.input_map.virtual_key_code(code))
@@ -97,6 +175,8 @@ pub struct ScriptSystemData<'a> {
     transforms: WriteStorage<'a, linear::Transform>,
     squares: WriteStorage<'a, shape::Square<gfx_device::Resources>>,
     cameras: WriteStorage<'a, camera::Camera2D>,
+    script_components: WriteStorage<'a, ScriptComponents>,
+    component_registry: ReadExpect<'a, ComponentRegistry>,
 }
 
 /// New type for specs entity to allow implementing traits.
@@ -99,7 +99,7 @@ impl UserData for Vector3f {
     }
 }
 
-#[derive(Component, Debug, Clone)]
+#[derive(Component, Debug, Clone, Reflection)]
 pub struct Transform {
     pub position: Vector3f,
 }
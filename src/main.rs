@@ -6,13 +6,17 @@ extern crate gfx_window_glutin as gfx_glutin;
 extern crate specs_derive;
 #[macro_use]
 extern crate shred_derive;
+#[macro_use]
+extern crate scriptable_derive;
 
+use crossbeam::channel::{bounded, Receiver, Sender};
 use gfx::{traits::FactoryExt, Device};
 use glutin::{dpi::LogicalSize, Api, GlRequest};
 use specs::prelude::*;
 
 mod camera;
 mod colors;
+mod console;
 mod delta_time;
 mod device_dim;
 mod draw;
@@ -22,6 +26,7 @@ mod input;
 mod linear;
 mod modding;
 mod physics;
+mod script_components;
 mod scriptable;
 mod shape;
 mod view_port;
@@ -84,6 +89,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     world.register::<linear::Transform>();
     world.register::<physics::Velocity>();
     world.register::<shape::Square<gfx_device::Resources>>();
+    world.register::<script_components::ScriptComponents>();
+    world.insert(script_components::ComponentRegistry::new());
 
     // Camera
     let camera_entity = camera::create_camera2d(&mut world);
@@ -94,23 +101,60 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Global scripting VM
     let mut lua = rlua::Lua::new();
+    let mut mod_hub = modding::ModHub::new();
+    println!("{}", mod_hub.settings());
+    mod_hub.install_budget(&lua);
     input::set_virtual_key_codes(&mut lua)?;
-    create_interface(&mut lua)?;
+    create_interface(&mut lua, &world, mod_hub.runner())?;
 
-    let mod_hub = modding::ModHub::new();
-    println!("{}", mod_hub.settings());
+    mod_hub.discover()?;
+    if let Err(err) = mod_hub.save_mod_list() {
+        eprintln!("failed saving mod list: {}", err);
+    }
+    if mod_hub.needs_debug_library() {
+        // The debug library can break memory safety, so it's only
+        // loaded when the user enabled a mod asking for it, and only
+        // that mod can see it.
+        println!("Loading the Lua debug library for trusted mods");
+        unsafe { lua.unsafe_load_from_std_lib(rlua::StdLib::DEBUG)? };
+    }
 
     run_maths_example(&mut lua)?;
-    test_scriptable_systems(&mut world)?;
 
-    let script_path = concat!(env!("CARGO_MANIFEST_DIR"), "/scripts/ecs_example.lua");
-    init_script(&mut lua, script_path, &mut world, factory.clone())?;
+    // Systems declared by mods
+    let (script_sender, script_receiver) = bounded(1);
+    let result = init_scripts(&mut lua, &mut mod_hub, &mut world, factory.clone()).and_then(|()| {
+        create_script_dispatcher(
+            &mut lua,
+            &mut mod_hub,
+            &mut world,
+            &script_sender,
+            &script_receiver,
+        )
+    });
+    let mut script_dispatcher = match result {
+        Ok(script_dispatcher) => script_dispatcher,
+        Err(err) => return shutdown_mods(&lua, &mut mod_hub, Err(err)).map_err(Into::into),
+    };
+    let mut enabled_scripts = mod_hub.enabled_scripts();
+    let commands = console::spawn();
+
+    if !mod_hub.report().is_empty() {
+        eprint!("{}", mod_hub.report());
+    }
+    if !mod_hub.names().conflicts().is_empty() {
+        eprint!("{}", mod_hub.names());
+    }
+    println!("Mod fingerprint {}", mod_hub.fingerprint());
 
     let mut encoder: gfx::Encoder<gfx_device::Resources, gfx_device::CommandBuffer> =
         factory.create_command_buffer().into();
 
     let mut running = true;
-    while running {
+    let result = loop {
+        if !running {
+            break Ok(());
+        }
         let start = std::time::Instant::now();
 
         world.write_resource::<input::InputStateMap>().clear();
@@ -198,7 +242,48 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         // Update //
         // ------ //
 
-        script_on_update(&mut lua, &mut world, factory.clone())?;
+        // Commands typed into the terminal
+        let result = lua.context(|lua_ctx| {
+            commands
+                .try_iter()
+                .try_for_each(|command| command.run(lua_ctx, &mut mod_hub))
+        });
+        if let Err(err) = result {
+            break Err(err);
+        }
+
+        if let Err(err) = script_on_update(&mut lua, &mut mod_hub, &mut world, factory.clone()) {
+            break Err(err);
+        }
+
+        // Systems are collected again when the enabled mods or their
+        // scripts changed
+        if mod_hub.enabled_scripts() != enabled_scripts {
+            let reported = mod_hub.report().errors().len();
+            script_dispatcher = match create_script_dispatcher(
+                &mut lua,
+                &mut mod_hub,
+                &mut world,
+                &script_sender,
+                &script_receiver,
+            ) {
+                Ok(script_dispatcher) => script_dispatcher,
+                Err(err) => break Err(err),
+            };
+            for err in &mod_hub.report().errors()[reported..] {
+                eprintln!("{}", err);
+            }
+            enabled_scripts = mod_hub.enabled_scripts();
+        }
+
+        // Script systems take turns with the scripting VM
+        script_sender
+            .send(lua)
+            .expect("failed sending scripting VM to script systems");
+        script_dispatcher.dispatch(&world);
+        lua = script_receiver
+            .recv()
+            .expect("failed retrieving scripting VM from script systems");
 
         // ------ //
         // Render //
@@ -215,13 +300,35 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         world.maintain();
 
         world.insert(DeltaTime::new(start.elapsed()));
-    }
+    };
+
+    shutdown_mods(&lua, &mut mod_hub, result)?;
 
     println!("Done!");
     Ok(())
 }
 
-fn create_interface(lua: &mut rlua::Lua) -> Result<(), rlua::Error> {
+/// Shuts the mods down, however the application exits.
+///
+/// The error that stopped the application, if any, takes precedence
+/// over errors raised while shutting down.
+fn shutdown_mods(
+    lua: &rlua::Lua,
+    mod_hub: &mut modding::ModHub,
+    result: rlua::Result<()>,
+) -> rlua::Result<()> {
+    let shutdown = lua.context(|lua_ctx| mod_hub.shutdown(lua_ctx));
+    result.and(shutdown)
+}
+
+fn create_interface(
+    lua: &mut rlua::Lua,
+    world: &World,
+    runner: modding::ModRunner,
+) -> Result<(), rlua::Error> {
+    let component_registry =
+        (*world.read_resource::<script_components::ComponentRegistry>()).clone();
+
     lua.context(|lua_ctx| {
         let globals = lua_ctx.globals();
 
@@ -235,6 +342,18 @@ fn create_interface(lua: &mut rlua::Lua) -> Result<(), rlua::Error> {
         let create_velocity = lua_ctx.create_function(|_, ()| Ok(physics::Velocity::zero()))?;
         globals.set("Velocity", create_velocity)?;
 
+        // Declares a component type, with the default value of each field,
+        // unless a mod with a higher priority declared it already
+        let define_component = lua_ctx.create_function(
+            move |_, (name, schema): (String, Option<script_components::ScriptFields>)| {
+                if runner.register_name(modding::ConflictKind::Component, &name)? {
+                    component_registry.define(name, schema.unwrap_or_default());
+                }
+                Ok(())
+            },
+        )?;
+        globals.set("component", define_component)?;
+
         // let load_image = lua_ctx
         //     .create_function(|_, file_path: String| Ok(sprite::Image::load(file_path).unwrap()))?;
         // globals.set("Image", load_image)?;
@@ -272,6 +391,7 @@ fn run_maths_example(lua: &mut rlua::Lua) -> rlua::Result<()> {
 
 fn script_on_update(
     lua: &mut rlua::Lua,
+    mod_hub: &mut modding::ModHub,
     world: &mut World,
     factory: gfx_device::Factory,
 ) -> rlua::Result<()> {
@@ -285,9 +405,12 @@ fn script_on_update(
             let proxy_user_data = scope.create_nonstatic_userdata(ecs_proxy)?;
             globals.set("proxy", proxy_user_data)?;
 
-            let update = globals.get::<_, rlua::Function>("on_update")?;
+            mod_hub.new_frame();
+            mod_hub.reload_changed(lua_ctx)?;
+
             // println!("Rust: on_update({})", dt);
-            update.call(dt)?;
+            mod_hub.call_hook(lua_ctx, "on_update", dt)?;
+            mod_hub.dispatch_events(lua_ctx)?;
 
             Ok(())
         })?;
@@ -296,43 +419,16 @@ fn script_on_update(
     })
 }
 
-fn load_script<P>(path: P) -> Option<String>
-where
-    P: AsRef<std::path::Path>,
-{
-    use std::io::prelude::*;
-    let p = path.as_ref();
-    let mut file = std::fs::File::open(p).unwrap();
-    let mut buf = String::new();
-    match file.read_to_string(&mut buf) {
-        Ok(_count) => Some(buf),
-        Err(err) => {
-            eprintln!(
-                "failed loading script at '{}': {}",
-                p.to_string_lossy(),
-                err
-            );
-            None
-        }
-    }
-}
-
-fn init_script<P>(
+fn init_scripts(
     lua: &mut rlua::Lua,
-    path: P,
+    mod_hub: &mut modding::ModHub,
     world: &mut World,
     factory: gfx_device::Factory,
-) -> rlua::Result<()>
-where
-    P: AsRef<std::path::Path>,
-{
-    println!("Initialize script '{}'", path.as_ref().to_string_lossy());
-    let script = load_script(path).expect("failed loading script");
-
+) -> rlua::Result<()> {
     let ecs_proxy = ecs::EcsProxy::new(world.system_data(), factory);
 
     lua.context(|lua_ctx| {
-        lua_ctx.load(&script).eval()?;
+        mod_hub.load_scripts(lua_ctx)?;
 
         lua_ctx.scope(|scope| {
             let globals = lua_ctx.globals();
@@ -341,10 +437,9 @@ where
             let proxy_user_data = scope.create_nonstatic_userdata(ecs_proxy)?;
             globals.set("proxy", proxy_user_data)?;
 
-            // Allow script to initialise itself
-            let on_init = globals.get::<_, rlua::Function>("on_init")?;
+            // Allow scripts to initialise themselves
             println!("Rust: on_init()");
-            on_init.call(())?;
+            mod_hub.call_hook(lua_ctx, "on_init", ())?;
 
             Ok(())
         })?;
@@ -353,59 +448,40 @@ where
     })
 }
 
-fn test_scriptable_systems(world: &mut World) -> rlua::Result<()> {
-    println!("======== test_scriptable_systems ========");
-    use crossbeam::channel::{bounded, Receiver, Sender};
-    use rlua::{prelude::*, Function, Table};
+/// Builds the dispatcher running the systems declared by the mods.
+///
+/// The systems borrow the scripting VM through the given channel while
+/// they run, so it must be sent before each dispatch and received back
+/// afterwards. Problems with the declared systems are added to the
+/// report of the mods.
+fn create_script_dispatcher(
+    lua: &mut rlua::Lua,
+    mod_hub: &mut modding::ModHub,
+    world: &mut World,
+    sender: &Sender<rlua::Lua>,
+    receiver: &Receiver<rlua::Lua>,
+) -> rlua::Result<Dispatcher<'static, 'static>> {
+    // Resources that scripts can access, by name
+    let mut resources = scriptable::ResourceTable::new();
+    resources.register::<DeltaTime>("DeltaTime");
 
-    use scriptable::*;
+    let mut reflection_table = scriptable::ReflectionTable::new();
+    reflection_table.register(&*world.read_resource::<DeltaTime>());
+    world.insert(reflection_table);
 
-    let mut lua = rlua::Lua::new();
-    let (tx, rx): (Sender<Lua>, Receiver<Lua>) = bounded(1);
     let mut builder = DispatcherBuilder::new();
-    // .with(ScriptSystem::new(tx.clone(), rx.clone(), ), "system_a", &[])
-
-    let script = r#"
-
-    local counter = 0
-
-    systems = {
-        process_a = {
-            reads = {},
-            writes = {},
-            run = function(data)
-                print("processing system_a " .. tostring(counter))
-                counter = counter + 1
-            end,
-        },
-    }
-    
-    "#;
-
     lua.context(|lua_ctx| {
-        // Load script
-        lua_ctx.load(script).exec()?;
-
-        Ok(())
-    })?;
-
-    lua.context(|lua_ctx| {
-        let globals = lua_ctx.globals();
-        let systems: Table = globals.get("systems")?;
-
-        for pair in systems.pairs::<String, Table>() {
-            let (_name, table) = pair?;
-            let _reads: Table = table.get("reads")?;
-            let _writes: Table = table.get("writes")?;
-            let run_func: Function = table.get("run")?;
-            let callback_key = lua_ctx.create_registry_value(run_func)?;
-            builder.add_thread_local(ScriptSystem::new(
-                tx.clone(),
-                rx.clone(),
-                callback_key,
-                &[],
-                &[],
-            ));
+        let (systems, mut errors) = scriptable::collect_systems(lua_ctx, mod_hub)?;
+        errors.extend(scriptable::add_systems(
+            &mut builder,
+            systems,
+            &resources,
+            &mod_hub.runner(),
+            sender,
+            receiver,
+        ));
+        for err in errors {
+            mod_hub.report_error(err);
         }
 
         Ok(())
@@ -414,22 +490,5 @@ fn test_scriptable_systems(world: &mut World) -> rlua::Result<()> {
     let mut dispatcher = builder.build();
     dispatcher.setup(world);
 
-    // Example process
-    println!("Running");
-    let mut lua_swap = Some(lua);
-
-    for _ in 0..10 {
-        tx.send(lua_swap.take().unwrap()).unwrap();
-        dispatcher.run_now(world);
-        lua_swap = Some(rx.recv().unwrap());
-    }
-
-    lua_swap.unwrap().context(|lua_ctx| {
-        // Clean up registry values
-        lua_ctx.expire_registry_values();
-
-        Ok(())
-    })?;
-
-    Ok(())
+    Ok(dispatcher)
 }
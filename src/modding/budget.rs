@@ -0,0 +1,344 @@
+//! Limits on the time and memory used by mod scripts
+
+use rlua::{Context, Function, HookTriggers, Lua, Table};
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+/// A mod that used more than its budget.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BudgetExceeded {
+    pub mod_name: String,
+    pub kind: BudgetKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BudgetKind {
+    /// Ran more instructions in one frame than allowed.
+    Instructions { budget: u64 },
+    /// Allocated more memory in one call than allowed.
+    Memory { budget: usize },
+}
+
+impl fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            BudgetKind::Instructions { budget } => write!(
+                f,
+                "mod '{}' ran more than {} instructions in one frame",
+                self.mod_name, budget
+            ),
+            BudgetKind::Memory { budget } => write!(
+                f,
+                "mod '{}' allocated more than {} bytes in one call",
+                self.mod_name, budget
+            ),
+        }
+    }
+}
+
+impl Error for BudgetExceeded {}
+
+/// Counts the instructions and memory used by each mod, and stops
+/// mods that go over their budget.
+///
+/// The Lua state is shared by all mods, so the meter must be told
+/// which mod is running with `enter` and `leave`. Every so many
+/// instructions, a hook checks the running mod against its budget
+/// and raises an error if it went over, which unwinds the mod's
+/// scripts back to the caller.
+///
+/// Once a mod went over its budget, every check fails until the mod
+/// returns, and the `pcall`, `xpcall` and `coroutine.resume` given to
+/// mods pass the error on, so that a mod can't keep running by
+/// catching it.
+#[derive(Clone)]
+pub struct BudgetMeter {
+    state: Arc<Mutex<MeterState>>,
+}
+
+struct MeterState {
+    /// Mods that are running, innermost last, with the memory in use
+    /// when each was entered.
+    running: Vec<(String, usize)>,
+    /// Instructions run by each mod in the current frame.
+    instructions: BTreeMap<String, u64>,
+    /// Budget that was exceeded by the innermost running mod.
+    exceeded: Option<BudgetExceeded>,
+    instruction_budget: u64,
+    memory_budget: usize,
+    check_interval: u32,
+}
+
+impl MeterState {
+    /// Budget exceeded by the innermost running mod, which is still
+    /// running.
+    fn running_exceeded(&self) -> Option<&BudgetExceeded> {
+        let (mod_name, _) = self.running.last()?;
+        self.exceeded
+            .as_ref()
+            .filter(|exceeded| exceeded.mod_name == *mod_name)
+    }
+}
+
+/// Versions of `pcall`, `xpcall` and `coroutine.resume` that pass
+/// errors on when the running mod went over its budget.
+const GUARDS_SOURCE: &str = r#"
+local over_budget = ...
+
+local function check(ok, ...)
+    if not ok and over_budget() then
+        error((...), 0)
+    end
+    return ok, ...
+end
+
+return {
+    pcall = function(...)
+        return check(pcall(...))
+    end,
+    xpcall = function(...)
+        return check(xpcall(...))
+    end,
+    resume = function(...)
+        return check(coroutine.resume(...))
+    end,
+}
+"#;
+
+const GUARDS_KEY: &str = "modding_budget_guards";
+
+impl BudgetMeter {
+    /// Creates a meter allowing every mod to run the given number of
+    /// instructions per frame, and allocate the given number of bytes
+    /// per call.
+    ///
+    /// Budgets are checked every `check_interval` instructions.
+    pub fn new(instruction_budget: u64, memory_budget: usize, check_interval: u32) -> Self {
+        BudgetMeter {
+            state: Arc::new(Mutex::new(MeterState {
+                running: Vec::new(),
+                instructions: BTreeMap::new(),
+                exceeded: None,
+                instruction_budget,
+                memory_budget,
+                check_interval,
+            })),
+        }
+    }
+
+    /// Installs the hook that enforces the budgets, replacing any
+    /// other hook of the Lua state.
+    pub fn install(&self, lua: &Lua) {
+        let state = self.state.clone();
+        let check_interval = state.lock().unwrap().check_interval;
+
+        lua.set_hook(
+            HookTriggers {
+                every_nth_instruction: Some(check_interval),
+                ..Default::default()
+            },
+            move |lua_ctx, _debug| {
+                let (mod_name, baseline, used_instructions, budgets) = {
+                    let mut state = state.lock().unwrap();
+                    if let Some(exceeded) = state.running_exceeded() {
+                        return Err(rlua::Error::RuntimeError(exceeded.to_string()));
+                    }
+                    let (mod_name, baseline) = match state.running.last() {
+                        Some(running) => running.clone(),
+                        None => return Ok(()),
+                    };
+
+                    let check_interval = u64::from(state.check_interval);
+                    let used = state.instructions.entry(mod_name.clone()).or_insert(0);
+                    *used += check_interval;
+                    let used = *used;
+
+                    (
+                        mod_name,
+                        baseline,
+                        used,
+                        (state.instruction_budget, state.memory_budget),
+                    )
+                };
+                let (instruction_budget, memory_budget) = budgets;
+
+                let kind = if used_instructions > instruction_budget {
+                    Some(BudgetKind::Instructions {
+                        budget: instruction_budget,
+                    })
+                } else if used_memory(lua_ctx)?.saturating_sub(baseline) > memory_budget {
+                    Some(BudgetKind::Memory {
+                        budget: memory_budget,
+                    })
+                } else {
+                    None
+                };
+
+                match kind {
+                    Some(kind) => {
+                        let exceeded = BudgetExceeded { mod_name, kind };
+                        let message = exceeded.to_string();
+                        state.lock().unwrap().exceeded = Some(exceeded);
+                        Err(rlua::Error::RuntimeError(message))
+                    }
+                    None => Ok(()),
+                }
+            },
+        );
+    }
+
+    /// Creates the table of `pcall`, `xpcall` and `resume` functions
+    /// that mods use in place of the standard ones, so that they can't
+    /// catch the error raised when they go over their budget.
+    pub fn create_guards<'lua>(&self, lua_ctx: Context<'lua>) -> rlua::Result<Table<'lua>> {
+        let create = match lua_ctx.named_registry_value::<_, Option<Function>>(GUARDS_KEY)? {
+            Some(func) => func,
+            None => {
+                let func: Function = lua_ctx
+                    .load(GUARDS_SOURCE)
+                    .set_name("budget_guards")?
+                    .into_function()?;
+                lua_ctx.set_named_registry_value(GUARDS_KEY, func.clone())?;
+                func
+            }
+        };
+
+        let state = self.state.clone();
+        let over_budget = lua_ctx
+            .create_function(move |_, ()| Ok(state.lock().unwrap().running_exceeded().is_some()))?;
+
+        create.call(over_budget)
+    }
+
+    /// Starts the next frame, resetting the instruction counts.
+    pub fn new_frame(&self) {
+        self.state.lock().unwrap().instructions.clear();
+    }
+
+    /// Records that the given mod's scripts are about to run.
+    pub fn enter(&self, lua_ctx: Context, mod_name: &str) -> rlua::Result<()> {
+        let used = used_memory(lua_ctx)?;
+        self.state
+            .lock()
+            .unwrap()
+            .running
+            .push((mod_name.to_owned(), used));
+        Ok(())
+    }
+
+    /// Records that the innermost running mod returned, and whether it
+    /// went over its budget.
+    pub fn leave(&self) -> Option<BudgetExceeded> {
+        let mut state = self.state.lock().unwrap();
+        let (mod_name, _) = state.running.pop()?;
+
+        match state.exceeded.take() {
+            Some(exceeded) if exceeded.mod_name == mod_name => Some(exceeded),
+            other => {
+                state.exceeded = other;
+                None
+            }
+        }
+    }
+
+    /// Innermost mod whose scripts are running, if any.
+    pub fn running(&self) -> Option<String> {
+        let state = self.state.lock().unwrap();
+        state.running.last().map(|(mod_name, _)| mod_name.clone())
+    }
+}
+
+/// Memory in use by the Lua state, in bytes.
+fn used_memory(lua_ctx: Context) -> rlua::Result<usize> {
+    let collect_garbage: Function = lua_ctx.globals().raw_get("collectgarbage")?;
+    let kilobytes: f64 = collect_garbage.call("count")?;
+    Ok((kilobytes * 1024.0) as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs a script as a mod with a small instruction budget, giving
+    /// it the guarded functions in place of the standard ones.
+    fn run_runaway(source: &str) -> (rlua::Result<()>, Option<BudgetExceeded>) {
+        let lua = Lua::new();
+        let budget = BudgetMeter::new(100_000, usize::MAX, 1000);
+        budget.install(&lua);
+
+        lua.context(|lua_ctx| {
+            let guards = budget.create_guards(lua_ctx).unwrap();
+            let environment = lua_ctx.create_table().unwrap();
+            environment
+                .set("pcall", guards.get::<_, Function>("pcall").unwrap())
+                .unwrap();
+            environment
+                .set("xpcall", guards.get::<_, Function>("xpcall").unwrap())
+                .unwrap();
+            let coroutine = lua_ctx.create_table().unwrap();
+            let real_coroutine: Table = lua_ctx.globals().get("coroutine").unwrap();
+            coroutine
+                .set(
+                    "create",
+                    real_coroutine.get::<_, Function>("create").unwrap(),
+                )
+                .unwrap();
+            coroutine
+                .set("resume", guards.get::<_, Function>("resume").unwrap())
+                .unwrap();
+            environment.set("coroutine", coroutine).unwrap();
+            let meta = lua_ctx.create_table().unwrap();
+            meta.set("__index", lua_ctx.globals()).unwrap();
+            environment.set_metatable(Some(meta));
+
+            budget.enter(lua_ctx, "runaway").unwrap();
+            let result = lua_ctx
+                .load(source)
+                .set_environment(environment)
+                .unwrap()
+                .exec();
+            (result, budget.leave())
+        })
+    }
+
+    #[test]
+    fn catching_the_budget_error_does_not_keep_a_mod_running() {
+        let sources = [
+            "while true do pcall(function() while true do end end) end",
+            "while true do xpcall(function() while true do end end, function() end) end",
+            "while true do coroutine.resume(coroutine.create(function() while true do end end)) end",
+        ];
+
+        for source in &sources {
+            let (result, exceeded) = run_runaway(source);
+
+            assert!(result.is_err(), "{}", source);
+            assert_eq!(
+                exceeded,
+                Some(BudgetExceeded {
+                    mod_name: "runaway".to_owned(),
+                    kind: BudgetKind::Instructions { budget: 100_000 },
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn guards_pass_other_errors_through() {
+        let (result, exceeded) = run_runaway(
+            r#"
+            local ok, err = pcall(error, "expected", 0)
+            assert(not ok and err == "expected")
+            local ok, value = pcall(function() return 1 end)
+            assert(ok and value == 1)
+            "#,
+        );
+
+        assert!(result.is_ok());
+        assert_eq!(exceeded, None);
+    }
+}
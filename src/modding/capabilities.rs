@@ -0,0 +1,278 @@
+//! Standard library access granted to mods
+
+use super::{environment, BudgetMeter, Vfs};
+use rlua::{Context, Function, Table, Value};
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// Standard library functions that a mod can use only when it declares
+/// the matching capability in its manifest.
+///
+/// ```toml
+/// capabilities = ["fs_read", "os_time"]
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    /// `fs.read`, `fs.exists`, `fs.source` and `fs.sources`, for the
+    /// mod's own files and the files shared by all mods.
+    FsRead,
+    /// `os.time`, `os.clock`, `os.date` and `os.difftime`.
+    OsTime,
+    /// The `debug` library, which the host only loads when an enabled
+    /// mod declares this capability.
+    ///
+    /// Only grant this to trusted mods. Besides reaching into other
+    /// mods and removing the budget hook, the debug library can break
+    /// the memory safety of the Rust host, for instance by replacing
+    /// the metatables of userdata or the upvalues of Rust callbacks.
+    Debug,
+}
+
+/// Globals of the standard library that are hidden from every mod.
+///
+/// Mods load code through their own `require`, which stays inside the
+/// mod and its dependencies. `rawset` would bypass the read-only views
+/// of library tables, which are shared by all mods, and `_G` would
+/// lead back to the unfiltered globals, so mods see their own
+/// environment under that name instead. Anything else in the shared
+/// globals, such as `string`, `table`, `math` or the engine's API,
+/// remains visible.
+const HIDDEN_GLOBALS: &[&str] = &[
+    "_G",
+    "collectgarbage",
+    "debug",
+    "dofile",
+    "io",
+    "load",
+    "loadfile",
+    "os",
+    "package",
+    "rawset",
+    "require",
+];
+
+const OS_TIME_FUNCTIONS: &[&str] = &["clock", "date", "difftime", "time"];
+
+/// Hides globals from a view of the shared globals, and adds others
+/// in their place.
+const SANDBOX_SOURCE: &str = r#"
+local shared, hidden, granted = ...
+
+-- Strings share a metatable whose `__index` is the real string
+-- library, so it's kept out of reach of `getmetatable`.
+local string_meta = getmetatable("")
+if type(string_meta) == "table" then
+    string_meta.__metatable = false
+end
+
+return setmetatable({}, {
+    __index = function(_, key)
+        local value = granted[key]
+        if value ~= nil then
+            return value
+        end
+        if hidden[key] then
+            return nil
+        end
+        return shared[key]
+    end,
+    __newindex = function(_, key)
+        error("attempt to modify read-only field '" .. tostring(key) .. "'", 2)
+    end,
+    __metatable = false,
+})
+"#;
+
+const SANDBOX_KEY: &str = "modding_sandbox";
+
+/// Creates the view of the shared globals seen by a mod with the given
+/// capabilities.
+///
+/// `shared` should already be read-only. Files in the directories of
+/// mods are read through `fs` from the mod that overrides them, while
+/// files at the root come from the mod itself. `pcall`,
+/// `xpcall` and `coroutine.resume` are replaced by the guards of the
+/// budget meter.
+pub fn create_sandbox<'lua>(
+    lua_ctx: Context<'lua>,
+    shared: Table<'lua>,
+    capabilities: &[Capability],
+    vfs: Arc<Vfs>,
+    budget: &BudgetMeter,
+    mod_name: &str,
+) -> rlua::Result<Table<'lua>> {
+    let hidden = lua_ctx.create_table()?;
+    for name in HIDDEN_GLOBALS {
+        hidden.set(*name, true)?;
+    }
+
+    let granted = lua_ctx.create_table()?;
+    let guards = budget.create_guards(lua_ctx)?;
+    granted.set("pcall", guards.get::<_, Function>("pcall")?)?;
+    granted.set("xpcall", guards.get::<_, Function>("xpcall")?)?;
+    if let Some(real_coroutine) = lua_ctx.globals().raw_get::<_, Option<Table>>("coroutine")? {
+        let coroutine = lua_ctx.create_table()?;
+        for pair in real_coroutine.pairs::<Value, Value>() {
+            let (name, value) = pair?;
+            coroutine.set(name, value)?;
+        }
+        coroutine.set("resume", guards.get::<_, Function>("resume")?)?;
+        granted.set("coroutine", environment::read_only(lua_ctx, coroutine)?)?;
+    }
+    for capability in capabilities {
+        match capability {
+            Capability::FsRead => {
+                granted.set("fs", create_fs(lua_ctx, vfs.clone(), mod_name)?)?;
+            }
+            Capability::OsTime => {
+                let os = lua_ctx.create_table()?;
+                if let Some(real_os) = lua_ctx.globals().raw_get::<_, Option<Table>>("os")? {
+                    for name in OS_TIME_FUNCTIONS {
+                        os.set(*name, real_os.raw_get::<_, Function>(*name)?)?;
+                    }
+                }
+                granted.set("os", environment::read_only(lua_ctx, os)?)?;
+            }
+            Capability::Debug => {
+                if let Some(debug) = lua_ctx.globals().raw_get::<_, Option<Table>>("debug")? {
+                    granted.set("debug", environment::read_only(lua_ctx, debug)?)?;
+                }
+            }
+        }
+    }
+
+    let sandbox = match lua_ctx.named_registry_value::<_, Option<Function>>(SANDBOX_KEY)? {
+        Some(func) => func,
+        None => {
+            let func: Function = lua_ctx
+                .load(SANDBOX_SOURCE)
+                .set_name("sandbox")?
+                .into_function()?;
+            lua_ctx.set_named_registry_value(SANDBOX_KEY, func.clone())?;
+            func
+        }
+    };
+
+    sandbox.call((shared, hidden, granted))
+}
+
+/// Creates the `fs` table, for reading the files of mods.
+///
+/// Paths in directories are shared by all mods, so they're read from
+/// the mod that overrides them, while files at the root of the mod are
+/// its own.
+///
+/// ```lua
+/// if fs.exists("data/levels.txt") then
+///     local levels = fs.read("data/levels.txt")
+///     print("levels from " .. fs.source("data/levels.txt"))
+/// end
+/// for path, mod_name in pairs(fs.sources()) do
+///     print(path .. " supplied by " .. mod_name)
+/// end
+/// ```
+fn create_fs<'lua>(
+    lua_ctx: Context<'lua>,
+    vfs: Arc<Vfs>,
+    mod_name: &str,
+) -> rlua::Result<Table<'lua>> {
+    let fs = lua_ctx.create_table()?;
+
+    let read_vfs = vfs.clone();
+    let read_mod_name = mod_name.to_owned();
+    let read = lua_ctx.create_function(move |_, path: String| {
+        read_vfs
+            .read_to_string_for(&read_mod_name, &path)
+            .map_err(|err| rlua::Error::RuntimeError(format!("failed reading '{}': {}", path, err)))
+    })?;
+    fs.set("read", read)?;
+
+    let exists_vfs = vfs.clone();
+    let exists_mod_name = mod_name.to_owned();
+    let exists = lua_ctx.create_function(move |_, path: String| {
+        Ok(exists_vfs.resolve_for(&exists_mod_name, &path).is_some())
+    })?;
+    fs.set("exists", exists)?;
+
+    let source_vfs = vfs.clone();
+    let source_mod_name = mod_name.to_owned();
+    let source = lua_ctx.create_function(move |_, path: String| {
+        Ok(source_vfs
+            .resolve_for(&source_mod_name, &path)
+            .map(str::to_owned))
+    })?;
+    fs.set("source", source)?;
+
+    let sources = lua_ctx.create_function(move |lua_ctx, ()| {
+        let sources = vfs.sources().map_err(|err| {
+            rlua::Error::RuntimeError(format!("failed listing files of mods: {}", err))
+        })?;
+        lua_ctx.create_table_from(sources)
+    })?;
+    fs.set("sources", sources)?;
+
+    environment::read_only(lua_ctx, fs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rlua::{Lua, StdLib};
+
+    #[test]
+    fn sandbox_hides_unsafe_globals() {
+        let lua = Lua::new();
+        unsafe { lua.unsafe_load_from_std_lib(StdLib::DEBUG).unwrap() };
+
+        lua.context(|lua_ctx| {
+            let shared = environment::read_only(lua_ctx, lua_ctx.globals()).unwrap();
+            let shared = create_sandbox(
+                lua_ctx,
+                shared,
+                &[],
+                Arc::new(Vfs::new()),
+                &BudgetMeter::new(u64::MAX, usize::MAX, 1000),
+                "probe",
+            )
+            .unwrap();
+            let environment = environment::create_environment(lua_ctx, shared).unwrap();
+
+            lua_ctx
+                .load(
+                    r#"
+                    local names = { "io", "os", "require", "debug", "load", "rawset" }
+                    local function check_hidden(globals)
+                        for _, name in ipairs(names) do
+                            assert(globals[name] == nil, name .. " is reachable")
+                            assert(rawget(globals, name) == nil, name .. " is reachable")
+                        end
+                    end
+
+                    check_hidden(_G)
+                    assert(_G._G == _G)
+
+                    -- `_G` can't be replaced or removed.
+                    assert(not pcall(function() _G._G = nil end))
+                    assert(not pcall(function() _G = {} end))
+                    assert(_G._G == _G)
+                    check_hidden(_G._G)
+
+                    -- Metatables leading to the shared globals are guarded.
+                    assert(getmetatable(_G) == false)
+                    assert(getmetatable(string) == false)
+                    assert(getmetatable("") == false)
+                    assert(not pcall(setmetatable, _G, nil))
+
+                    -- Globals defined by the mod still work.
+                    answer = 42
+                    assert(rawget(_G, "answer") == 42)
+                    "#,
+                )
+                .set_environment(environment)
+                .unwrap()
+                .exec()
+                .unwrap();
+        });
+    }
+}
@@ -0,0 +1,221 @@
+//! Detection of mods registering the same names
+
+use std::{collections::BTreeMap, error::Error, fmt};
+
+/// Kinds of named things that mods register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ConflictKind {
+    /// Function defined in the global table of a mod.
+    GlobalFunction,
+    /// Event name declared in the manifest.
+    Event,
+    /// Prefab name declared in the manifest.
+    Prefab,
+    /// Component name declared in the manifest.
+    Component,
+    /// Path of a file in a directory of the mod, including scripts,
+    /// which other mods see in place of their own.
+    ///
+    /// Files at the root of a mod, such as the manifest or a readme,
+    /// describe the mod itself rather than being assets.
+    Asset,
+}
+
+impl fmt::Display for ConflictKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ConflictKind::GlobalFunction => "global function",
+            ConflictKind::Event => "event",
+            ConflictKind::Prefab => "prefab",
+            ConflictKind::Component => "component",
+            ConflictKind::Asset => "asset",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// What to do when a mod registers a name that another mod already
+/// registered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Report the conflict and let the later mod take over the name.
+    Warn,
+    /// Refuse to load the later mod.
+    Error,
+    /// Let the mod with the higher priority keep the name. Mods of
+    /// equal priority are reported as with `Warn`.
+    PriorityWins,
+}
+
+/// How a conflict was settled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    /// The later mod took over the name, with a warning.
+    Warned,
+    /// The later mod was not loaded.
+    Rejected,
+    /// The mod with the higher priority kept the name.
+    Overridden { winner: String },
+}
+
+/// Two mods registering the same name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub kind: ConflictKind,
+    pub name: String,
+    /// Mod that registered the name first.
+    pub existing: String,
+    /// Mod that registered the name again.
+    pub mod_name: String,
+    pub resolution: Resolution,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "mods '{}' and '{}' both register {} '{}', ",
+            self.existing, self.mod_name, self.kind, self.name
+        )?;
+
+        match &self.resolution {
+            Resolution::Warned => write!(f, "using the one from '{}'", self.mod_name),
+            Resolution::Rejected => write!(f, "so '{}' was not loaded", self.mod_name),
+            Resolution::Overridden { winner } => {
+                write!(f, "'{}' wins by priority", winner)
+            }
+        }
+    }
+}
+
+impl Error for Conflict {}
+
+struct Owner {
+    mod_name: String,
+    priority: u16,
+}
+
+/// Records which mod registered each name, and the conflicts found
+/// along the way.
+#[derive(Default)]
+pub struct NameRegistry {
+    owners: BTreeMap<(ConflictKind, String), Owner>,
+    conflicts: Vec<Conflict>,
+}
+
+impl NameRegistry {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Mod that currently owns the given name.
+    pub fn owner(&self, kind: ConflictKind, name: &str) -> Option<&str> {
+        self.owners
+            .get(&(kind, name.to_owned()))
+            .map(|owner| owner.mod_name.as_str())
+    }
+
+    pub fn conflicts(&self) -> &[Conflict] {
+        &self.conflicts
+    }
+
+    /// Registers a name for a mod, applying the policy if another mod
+    /// registered it already.
+    ///
+    /// Returns the conflict when the policy rejects the mod. The mod's
+    /// other registrations are left in place, so the caller should
+    /// remove them with `unregister_mod`.
+    pub fn register(
+        &mut self,
+        kind: ConflictKind,
+        name: &str,
+        mod_name: &str,
+        priority: u16,
+        policy: ConflictPolicy,
+    ) -> Result<(), Conflict> {
+        let key = (kind, name.to_owned());
+        let owner = Owner {
+            mod_name: mod_name.to_owned(),
+            priority,
+        };
+
+        let existing = match self.owners.get(&key) {
+            Some(existing) if existing.mod_name != mod_name => existing,
+            _ => {
+                self.owners.insert(key, owner);
+                return Ok(());
+            }
+        };
+
+        let resolution = match policy {
+            ConflictPolicy::Error => Resolution::Rejected,
+            ConflictPolicy::PriorityWins if existing.priority > priority => {
+                Resolution::Overridden {
+                    winner: existing.mod_name.clone(),
+                }
+            }
+            ConflictPolicy::PriorityWins if existing.priority < priority => {
+                Resolution::Overridden {
+                    winner: mod_name.to_owned(),
+                }
+            }
+            ConflictPolicy::PriorityWins | ConflictPolicy::Warn => Resolution::Warned,
+        };
+        let conflict = Conflict {
+            kind,
+            name: name.to_owned(),
+            existing: existing.mod_name.clone(),
+            mod_name: mod_name.to_owned(),
+            resolution,
+        };
+        self.conflicts.push(conflict.clone());
+
+        match &conflict.resolution {
+            Resolution::Rejected => return Err(conflict),
+            Resolution::Overridden { winner } if winner != mod_name => {}
+            _ => {
+                self.owners.insert(key, owner);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Removes the names of the given kind registered by a mod, along
+    /// with the conflicts they caused.
+    pub fn unregister(&mut self, kind: ConflictKind, mod_name: &str) {
+        self.owners
+            .retain(|(k, _), owner| *k != kind || owner.mod_name != mod_name);
+        self.conflicts.retain(|conflict| {
+            conflict.kind != kind
+                || (conflict.existing != mod_name && conflict.mod_name != mod_name)
+        });
+    }
+
+    /// Removes every name registered by a mod.
+    ///
+    /// Conflicts that rejected the mod are kept, so they can still be
+    /// reported.
+    pub fn unregister_mod(&mut self, mod_name: &str) {
+        self.owners.retain(|_, owner| owner.mod_name != mod_name);
+        self.conflicts.retain(|conflict| {
+            conflict.resolution == Resolution::Rejected
+                || (conflict.existing != mod_name && conflict.mod_name != mod_name)
+        });
+    }
+}
+
+impl fmt::Display for NameRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.conflicts.is_empty() {
+            return write!(f, "no conflicts between mods");
+        }
+
+        writeln!(f, "{} conflict(s) between mods:", self.conflicts.len())?;
+        for conflict in &self.conflicts {
+            writeln!(f, "  - {}", conflict)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,120 @@
+//! Per-mod script environments
+
+use rlua::{Context, Function, Table};
+
+/// Wraps a table in a proxy that can be read but not modified.
+///
+/// Nested tables are wrapped as well when they are accessed, so
+/// shared library tables like `string` or `virtual_key_code` can't
+/// be changed through the proxy either.
+const READ_ONLY_SOURCE: &str = r#"
+local cache = setmetatable({}, { __mode = "k" })
+
+local function read_only(target)
+    local proxy = cache[target]
+    if proxy then
+        return proxy
+    end
+
+    local function wrap(value)
+        if type(value) == "table" then
+            return read_only(value)
+        end
+        return value
+    end
+
+    proxy = setmetatable({}, {
+        __index = function(_, key)
+            return wrap(target[key])
+        end,
+        __newindex = function(_, key)
+            error("attempt to modify read-only field '" .. tostring(key) .. "'", 2)
+        end,
+        __len = function()
+            return #target
+        end,
+        __pairs = function()
+            return function(_, key)
+                local next_key, value = next(target, key)
+                return next_key, wrap(value)
+            end, proxy, nil
+        end,
+        __metatable = false,
+    })
+    cache[target] = proxy
+
+    return proxy
+end
+
+return read_only
+"#;
+
+const READ_ONLY_KEY: &str = "modding_read_only";
+
+/// Creates a read-only view of the given table.
+pub fn read_only<'lua>(lua_ctx: Context<'lua>, table: Table<'lua>) -> rlua::Result<Table<'lua>> {
+    let read_only = match lua_ctx.named_registry_value::<_, Option<Function>>(READ_ONLY_KEY)? {
+        Some(func) => func,
+        None => {
+            let func: Function = lua_ctx
+                .load(READ_ONLY_SOURCE)
+                .set_name("read_only")?
+                .eval()?;
+            lua_ctx.set_named_registry_value(READ_ONLY_KEY, func.clone())?;
+            func
+        }
+    };
+
+    read_only.call(table)
+}
+
+/// Global table of a mod, which falls through to the shared API.
+///
+/// `_G` isn't stored in the table, so the mod can't replace it, and
+/// always refers to the mod's own globals.
+const ENVIRONMENT_SOURCE: &str = r#"
+local shared = ...
+local environment = {}
+
+return setmetatable(environment, {
+    __index = function(_, key)
+        if key == "_G" then
+            return environment
+        end
+        return shared[key]
+    end,
+    __newindex = function(_, key, value)
+        if key == "_G" then
+            error("attempt to modify read-only field '_G'", 2)
+        end
+        rawset(environment, key, value)
+    end,
+    __metatable = false,
+})
+"#;
+
+const ENVIRONMENT_KEY: &str = "modding_environment";
+
+/// Creates the global environment for the scripts of a single mod.
+///
+/// Globals defined by the mod's scripts are stored in the environment
+/// itself. Lookups that miss fall through to the shared API, which the
+/// mod can read but not modify.
+pub fn create_environment<'lua>(
+    lua_ctx: Context<'lua>,
+    shared: Table<'lua>,
+) -> rlua::Result<Table<'lua>> {
+    let create = match lua_ctx.named_registry_value::<_, Option<Function>>(ENVIRONMENT_KEY)? {
+        Some(func) => func,
+        None => {
+            let func: Function = lua_ctx
+                .load(ENVIRONMENT_SOURCE)
+                .set_name("environment")?
+                .into_function()?;
+            lua_ctx.set_named_registry_value(ENVIRONMENT_KEY, func.clone())?;
+            func
+        }
+    };
+
+    create.call(shared)
+}
@@ -0,0 +1,315 @@
+//! Errors raised while loading mods
+
+use super::{BudgetExceeded, Conflict, DependencyError, ManifestError, SettingError};
+use std::{error::Error, fmt, io, path::PathBuf};
+
+#[derive(Debug)]
+pub enum ModLoadError {
+    /// The mod's manifest could not be read or parsed.
+    Manifest(ManifestError),
+    /// The mod's dependencies can't be satisfied.
+    Dependency(Box<DependencyError>),
+    /// Another mod with the same name was found first, so this one is
+    /// skipped.
+    Duplicate {
+        mod_name: String,
+        path: PathBuf,
+        other: PathBuf,
+    },
+    /// The entry point script named by the mod does not exist.
+    MissingEntryPoint {
+        mod_name: String,
+        entry_point: String,
+    },
+    /// The mod registers a name that another mod registered already,
+    /// and the conflict policy rejects it.
+    Conflict(Conflict),
+    /// The mod used more than its budget, and was suspended.
+    Budget(BudgetExceeded),
+    /// A value chosen by the user for one of the mod's settings is
+    /// invalid, so the default is used instead.
+    Setting(SettingError),
+    /// A mod or one of its files could not be read.
+    Io { path: PathBuf, error: io::Error },
+    /// A script of the mod could not be compiled.
+    Syntax {
+        mod_name: String,
+        file: String,
+        line: Option<u32>,
+        message: String,
+    },
+    /// A script of the mod raised an error while executing.
+    Script {
+        mod_name: String,
+        file: String,
+        error: Box<rlua::Error>,
+    },
+    /// A system declared by the mod uses a resource the host doesn't
+    /// provide, so it was left out.
+    UnknownResource {
+        mod_name: String,
+        system: String,
+        resource: String,
+    },
+    /// The systems declared by the mod are invalid, or can't be added
+    /// in the order they ask for.
+    System { mod_name: String, message: String },
+}
+
+impl ModLoadError {
+    /// Classifies an error raised by executing one of a mod's scripts.
+    ///
+    /// `file` is the path of the script, relative to the mod's root.
+    /// Errors raised inside Rust callbacks, such as `require`, are
+    /// classified by their cause, and conflicts raised by callbacks
+    /// registering names are reported as such.
+    pub fn from_lua(mod_name: &str, file: &str, error: rlua::Error) -> Self {
+        let mut cause = &error;
+        while let rlua::Error::CallbackError { cause: inner, .. } = cause {
+            cause = inner;
+        }
+
+        if let rlua::Error::ExternalError(external) = cause {
+            if let Some(conflict) = external.downcast_ref::<Conflict>() {
+                return ModLoadError::Conflict(conflict.clone());
+            }
+        }
+
+        match cause {
+            rlua::Error::SyntaxError { message, .. } => {
+                // Messages are formatted as `chunk:line: message`, where
+                // the chunk is named `mod/file`. The error may come from
+                // a module required by the script, so the file is taken
+                // from the message when possible.
+                let location = message
+                    .strip_prefix(mod_name)
+                    .and_then(|rest| rest.strip_prefix('/'))
+                    .and_then(|rest| {
+                        let mut parts = rest.splitn(3, ':');
+                        let file = parts.next()?;
+                        let line = parts.next()?.parse().ok()?;
+                        let message = parts.next()?.trim();
+                        Some((file.to_owned(), line, message.to_owned()))
+                    });
+                let (file, line, message) = match location {
+                    Some((file, line, message)) => (file, Some(line), message),
+                    None => (file.to_owned(), None, message.clone()),
+                };
+
+                ModLoadError::Syntax {
+                    mod_name: mod_name.to_owned(),
+                    file,
+                    line,
+                    message,
+                }
+            }
+            _ => ModLoadError::Script {
+                mod_name: mod_name.to_owned(),
+                file: file.to_owned(),
+                error: Box::new(error),
+            },
+        }
+    }
+
+    /// Name of the mod that failed, when known.
+    pub fn mod_name(&self) -> Option<&str> {
+        match self {
+            ModLoadError::Manifest(_) | ModLoadError::Io { .. } => None,
+            ModLoadError::Dependency(err) => match &**err {
+                DependencyError::Cycle { .. } => None,
+                DependencyError::Missing { mod_name, .. }
+                | DependencyError::Disabled { mod_name, .. }
+                | DependencyError::Unavailable { mod_name, .. }
+                | DependencyError::Version { mod_name, .. }
+                | DependencyError::Api { mod_name, .. } => Some(mod_name),
+            },
+            ModLoadError::Duplicate { mod_name, .. }
+            | ModLoadError::MissingEntryPoint { mod_name, .. }
+            | ModLoadError::Syntax { mod_name, .. }
+            | ModLoadError::Script { mod_name, .. }
+            | ModLoadError::UnknownResource { mod_name, .. }
+            | ModLoadError::System { mod_name, .. } => Some(mod_name),
+            ModLoadError::Setting(err) => Some(&err.mod_name),
+            ModLoadError::Conflict(conflict) => Some(&conflict.mod_name),
+            ModLoadError::Budget(exceeded) => Some(&exceeded.mod_name),
+        }
+    }
+
+    /// Checks whether the error concerns the given mod.
+    pub fn involves(&self, name: &str) -> bool {
+        match self {
+            ModLoadError::Dependency(err) => match &**err {
+                DependencyError::Cycle { mods } => mods.iter().any(|m| m == name),
+                _ => self.mod_name() == Some(name),
+            },
+            err => err.mod_name() == Some(name),
+        }
+    }
+}
+
+impl fmt::Display for ModLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ModLoadError::Manifest(err) => write!(f, "{}", err),
+            ModLoadError::Dependency(err) => write!(f, "{}", err),
+            ModLoadError::Setting(err) => write!(f, "{}", err),
+            ModLoadError::Conflict(conflict) => write!(f, "{}", conflict),
+            ModLoadError::Budget(exceeded) => write!(f, "{}, so it was suspended", exceeded),
+            ModLoadError::Duplicate {
+                mod_name,
+                path,
+                other,
+            } => write!(
+                f,
+                "mod '{}' in '{}' has the same name as the mod in '{}', so it was skipped",
+                mod_name,
+                path.to_string_lossy(),
+                other.to_string_lossy()
+            ),
+            ModLoadError::MissingEntryPoint {
+                mod_name,
+                entry_point,
+            } => write!(f, "mod '{}' has no entry point '{}'", mod_name, entry_point),
+            ModLoadError::Io { path, error } => {
+                write!(f, "failed reading '{}': {}", path.to_string_lossy(), error)
+            }
+            ModLoadError::Syntax {
+                mod_name,
+                file,
+                line: Some(line),
+                message,
+            } => write!(
+                f,
+                "syntax error in mod '{}' at {}:{}: {}",
+                mod_name, file, line, message
+            ),
+            ModLoadError::Syntax {
+                mod_name,
+                file,
+                line: None,
+                message,
+            } => write!(
+                f,
+                "syntax error in mod '{}' in {}: {}",
+                mod_name, file, message
+            ),
+            ModLoadError::Script {
+                mod_name,
+                file,
+                error,
+            } => write!(f, "error in mod '{}' running {}: {}", mod_name, file, error),
+            ModLoadError::UnknownResource {
+                mod_name,
+                system,
+                resource,
+            } => write!(
+                f,
+                "system '{}' of mod '{}' uses unknown resource '{}', so it was left out",
+                system, mod_name, resource
+            ),
+            ModLoadError::System { mod_name, message } => {
+                write!(f, "systems of mod '{}': {}", mod_name, message)
+            }
+        }
+    }
+}
+
+impl Error for ModLoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ModLoadError::Manifest(err) => Some(err),
+            ModLoadError::Dependency(err) => Some(&**err),
+            ModLoadError::Setting(err) => Some(err),
+            ModLoadError::Conflict(conflict) => Some(conflict),
+            ModLoadError::Budget(exceeded) => Some(exceeded),
+            ModLoadError::Io { error, .. } => Some(error),
+            ModLoadError::Script { error, .. } => Some(&**error),
+            ModLoadError::Duplicate { .. }
+            | ModLoadError::MissingEntryPoint { .. }
+            | ModLoadError::Syntax { .. }
+            | ModLoadError::UnknownResource { .. }
+            | ModLoadError::System { .. } => None,
+        }
+    }
+}
+
+impl From<ManifestError> for ModLoadError {
+    fn from(err: ManifestError) -> Self {
+        ModLoadError::Manifest(err)
+    }
+}
+
+impl From<BudgetExceeded> for ModLoadError {
+    fn from(exceeded: BudgetExceeded) -> Self {
+        ModLoadError::Budget(exceeded)
+    }
+}
+
+impl From<Conflict> for ModLoadError {
+    fn from(conflict: Conflict) -> Self {
+        ModLoadError::Conflict(conflict)
+    }
+}
+
+impl From<SettingError> for ModLoadError {
+    fn from(err: SettingError) -> Self {
+        ModLoadError::Setting(err)
+    }
+}
+
+impl From<DependencyError> for ModLoadError {
+    fn from(err: DependencyError) -> Self {
+        ModLoadError::Dependency(Box::new(err))
+    }
+}
+
+/// Problems collected while discovering, loading and running mods.
+#[derive(Debug, Default)]
+pub struct LoadReport {
+    errors: Vec<ModLoadError>,
+}
+
+impl LoadReport {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn push<E>(&mut self, error: E)
+    where
+        E: Into<ModLoadError>,
+    {
+        self.errors.push(error.into());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn errors(&self) -> &[ModLoadError] {
+        &self.errors
+    }
+
+    /// Errors that concern the given mod.
+    pub fn errors_for<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a ModLoadError> {
+        self.errors.iter().filter(move |err| err.involves(name))
+    }
+
+    pub fn clear(&mut self) {
+        self.errors.clear();
+    }
+}
+
+impl fmt::Display for LoadReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.errors.is_empty() {
+            return write!(f, "all mods loaded");
+        }
+
+        writeln!(f, "{} problem(s) with mods:", self.errors.len())?;
+        for err in &self.errors {
+            writeln!(f, "  - {}", err)?;
+        }
+
+        Ok(())
+    }
+}
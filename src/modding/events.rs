@@ -0,0 +1,208 @@
+//! Named events passed between mods
+
+use super::{BudgetExceeded, BudgetMeter, ScriptValue};
+use rlua::{Context, Function, RegistryKey, Table};
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    mem,
+    sync::{Arc, Mutex},
+};
+
+/// Queue of events emitted by mods, and the callbacks subscribed to them.
+///
+/// Events are queued when emitted and delivered together by
+/// `dispatch`. Each callback receives its own copy of the payload,
+/// so a mod can't change the tables seen by another mod.
+#[derive(Default)]
+pub struct EventBus {
+    queue: VecDeque<Event>,
+    /// Callbacks of each event, in the order they subscribed.
+    subscribers: BTreeMap<String, Vec<Subscriber>>,
+    /// Mods whose callbacks are skipped, because they are disabled.
+    muted: BTreeSet<String>,
+}
+
+struct Subscriber {
+    /// Name of the mod that subscribed.
+    mod_name: String,
+    /// Version of the mod's scripts that subscribed.
+    generation: u32,
+    callback: RegistryKey,
+}
+
+struct Event {
+    name: String,
+    /// Name of the mod that emitted the event.
+    sender: String,
+    payload: ScriptValue,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn emit<S>(&mut self, sender: S, name: S, payload: ScriptValue)
+    where
+        S: Into<String>,
+    {
+        self.queue.push_back(Event {
+            name: name.into(),
+            sender: sender.into(),
+            payload,
+        });
+    }
+
+    pub fn subscribe<S>(&mut self, mod_name: S, generation: u32, name: S, callback: RegistryKey)
+    where
+        S: Into<String>,
+    {
+        self.subscribers
+            .entry(name.into())
+            .or_default()
+            .push(Subscriber {
+                mod_name: mod_name.into(),
+                generation,
+                callback,
+            });
+    }
+
+    /// Removes the callbacks subscribed by one version of a mod's scripts.
+    pub fn unsubscribe(&mut self, mod_name: &str, generation: u32) {
+        for subscribers in self.subscribers.values_mut() {
+            subscribers.retain(|subscriber| {
+                subscriber.mod_name != mod_name || subscriber.generation != generation
+            });
+        }
+    }
+
+    /// Stops or resumes delivering events to a mod's callbacks.
+    ///
+    /// Events delivered while a mod is muted are not delivered to it
+    /// later.
+    pub fn set_muted(&mut self, mod_name: &str, muted: bool) {
+        if muted {
+            self.muted.insert(mod_name.to_owned());
+        } else {
+            self.muted.remove(mod_name);
+        }
+    }
+}
+
+/// An event callback that didn't return normally.
+#[derive(Debug)]
+pub enum CallbackFailure {
+    /// The mod went over its budget.
+    Budget(BudgetExceeded),
+    /// The callback raised an error.
+    Error {
+        mod_name: String,
+        error: rlua::Error,
+    },
+}
+
+impl CallbackFailure {
+    pub fn mod_name(&self) -> &str {
+        match self {
+            CallbackFailure::Budget(exceeded) => &exceeded.mod_name,
+            CallbackFailure::Error { mod_name, .. } => mod_name,
+        }
+    }
+}
+
+/// Delivers all queued events, in the order they were emitted.
+///
+/// Callbacks for the same event are called in the order they
+/// subscribed, with the payload and the name of the sending mod.
+/// Events emitted during dispatch are queued for the next call.
+///
+/// Returns the callbacks that went over their budget or raised an
+/// error. The rest of the queue is still delivered, but the other
+/// callbacks of a mod that failed are skipped, like those of muted
+/// mods.
+pub fn dispatch(
+    bus: &Mutex<EventBus>,
+    lua_ctx: Context,
+    budget: &BudgetMeter,
+) -> rlua::Result<Vec<CallbackFailure>> {
+    let mut failures = Vec::new();
+    let queue = mem::take(&mut bus.lock().unwrap().queue);
+
+    for event in queue {
+        // Callbacks are fetched up front so the bus isn't locked
+        // while they run, as they may emit events themselves.
+        let locked = bus.lock().unwrap();
+        let callbacks = locked
+            .subscribers
+            .get(&event.name)
+            .into_iter()
+            .flatten()
+            .filter(|subscriber| !locked.muted.contains(&subscriber.mod_name))
+            .map(|subscriber| {
+                let callback = lua_ctx.registry_value::<Function>(&subscriber.callback)?;
+                Ok((subscriber.mod_name.clone(), callback))
+            })
+            .collect::<rlua::Result<Vec<_>>>()?;
+        drop(locked);
+
+        for (mod_name, callback) in callbacks {
+            if failures
+                .iter()
+                .any(|failure: &CallbackFailure| failure.mod_name() == mod_name)
+            {
+                continue;
+            }
+
+            budget.enter(lua_ctx, &mod_name)?;
+            let result = callback.call::<_, ()>((event.payload.clone(), event.sender.as_str()));
+            match (budget.leave(), result) {
+                (Some(exceeded), _) => failures.push(CallbackFailure::Budget(exceeded)),
+                (None, Err(error)) => failures.push(CallbackFailure::Error { mod_name, error }),
+                (None, Ok(())) => {}
+            }
+        }
+    }
+
+    Ok(failures)
+}
+
+/// Creates the `events` table through which a mod emits and
+/// subscribes to events.
+///
+/// ```lua
+/// events.on("door_opened", function(payload, sender) end)
+/// events.emit("door_opened", { id = 1 })
+/// ```
+pub fn create_api<'lua>(
+    lua_ctx: Context<'lua>,
+    bus: Arc<Mutex<EventBus>>,
+    mod_name: &str,
+    generation: u32,
+) -> rlua::Result<Table<'lua>> {
+    let api = lua_ctx.create_table()?;
+
+    let emit_bus = bus.clone();
+    let emit_mod_name = mod_name.to_owned();
+    let emit =
+        lua_ctx.create_function(move |_, (name, payload): (String, Option<ScriptValue>)| {
+            emit_bus.lock().unwrap().emit(
+                emit_mod_name.clone(),
+                name,
+                payload.unwrap_or(ScriptValue::Nil),
+            );
+            Ok(())
+        })?;
+    api.set("emit", emit)?;
+
+    let on_mod_name = mod_name.to_owned();
+    let on = lua_ctx.create_function(move |lua_ctx, (name, callback): (String, Function)| {
+        let callback = lua_ctx.create_registry_value(callback)?;
+        bus.lock()
+            .unwrap()
+            .subscribe(on_mod_name.clone(), generation, name, callback);
+        Ok(())
+    })?;
+    api.set("on", on)?;
+
+    Ok(api)
+}
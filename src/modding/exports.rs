@@ -0,0 +1,139 @@
+//! Functions and data that mods publish for their dependents
+
+use super::{environment, require, Fingerprint, ScriptValue};
+use rlua::{Context, Function, Table, Value, Variadic};
+use std::sync::{Arc, Mutex};
+
+/// Exports of a mod, checked for before every call so a reloaded mod
+/// serves its new functions.
+fn exports<'lua>(lua_ctx: Context<'lua>, mod_name: &str) -> rlua::Result<Table<'lua>> {
+    let environment = require::registered_environment(lua_ctx, mod_name)?
+        .ok_or_else(|| rlua::Error::RuntimeError(format!("mod '{}' is not loaded", mod_name)))?;
+
+    match environment.raw_get::<_, Value>("exports")? {
+        Value::Table(exports) => Ok(exports),
+        Value::Nil => Err(rlua::Error::RuntimeError(format!(
+            "mod '{}' has no exports",
+            mod_name
+        ))),
+        _ => Err(rlua::Error::RuntimeError(format!(
+            "exports of mod '{}' are not a table",
+            mod_name
+        ))),
+    }
+}
+
+/// Creates a function that calls an exported function of another mod.
+///
+/// Arguments and results are copied, like event payloads, so neither
+/// mod can change the tables of the other. Errors raised by the
+/// exported function are prefixed with the name of its mod.
+fn create_call<'lua>(
+    lua_ctx: Context<'lua>,
+    mod_name: &str,
+    function: &str,
+) -> rlua::Result<Function<'lua>> {
+    let mod_name = mod_name.to_owned();
+    let function = function.to_owned();
+
+    lua_ctx.create_function(move |lua_ctx, args: Variadic<ScriptValue>| {
+        let error = |message: String| {
+            rlua::Error::RuntimeError(format!(
+                "error in '{}' exported by mod '{}': {}",
+                function, mod_name, message
+            ))
+        };
+
+        let target: Function = match exports(lua_ctx, &mod_name)?.raw_get(function.as_str())? {
+            Value::Function(target) => target,
+            _ => return Err(error("no longer exported".to_owned())),
+        };
+
+        target
+            .call::<_, Variadic<ScriptValue>>(args)
+            .map_err(|err| match err {
+                rlua::Error::RuntimeError(message) => error(message),
+                err => error(err.to_string()),
+            })
+    })
+}
+
+/// Creates the `mods` table through which a mod reaches the exports
+/// of its dependencies, and the fingerprints of the loaded mods.
+///
+/// ```lua
+/// -- enemylib/init.lua
+/// exports = {
+///     max_enemies = 10,
+///     spawn_enemy = function(kind, position) end,
+/// }
+///
+/// -- a mod that depends on enemylib
+/// local enemylib = mods.get("enemylib")
+/// enemylib.spawn_enemy("slime", { x = 1, y = 2 })
+///
+/// print(mods.fingerprint(), mods.fingerprint("enemylib"))
+/// ```
+///
+/// Only declared dependencies can be reached. Exported functions run
+/// in their own mod's environment, while their instructions count
+/// towards the budget of the calling mod.
+pub fn create_api<'lua>(
+    lua_ctx: Context<'lua>,
+    mod_name: &str,
+    dependencies: Vec<String>,
+    fingerprints: Arc<Mutex<Vec<(String, Fingerprint)>>>,
+) -> rlua::Result<Table<'lua>> {
+    let api = lua_ctx.create_table()?;
+
+    let requester = mod_name.to_owned();
+    let get = lua_ctx.create_function(move |lua_ctx, name: String| {
+        if !dependencies.contains(&name) {
+            return Err(rlua::Error::RuntimeError(format!(
+                "mod '{}' can't use the exports of '{}': '{}' is not a declared dependency",
+                requester, name, name
+            )));
+        }
+
+        let proxy = lua_ctx.create_table()?;
+        for pair in exports(lua_ctx, &name)?.pairs::<String, Value>() {
+            let (key, value) = pair?;
+            let value = match value {
+                Value::Function(_) => Value::Function(create_call(lua_ctx, &name, &key)?),
+                value => lua_ctx
+                    .unpack::<ScriptValue>(value)
+                    .and_then(|value| lua_ctx.pack(value))
+                    .map_err(|err| {
+                        rlua::Error::RuntimeError(format!(
+                            "can't copy '{}' exported by mod '{}': {}",
+                            key, name, err
+                        ))
+                    })?,
+            };
+            proxy.raw_set(key, value)?;
+        }
+
+        environment::read_only(lua_ctx, proxy)
+    })?;
+    api.set("get", get)?;
+
+    // Without a name, the fingerprint of all mods in the load order.
+    let fingerprint = lua_ctx.create_function(move |_, name: Option<String>| {
+        let fingerprints = fingerprints.lock().unwrap();
+        let fingerprint = match name {
+            Some(name) => fingerprints
+                .iter()
+                .find(|(mod_name, _)| *mod_name == name)
+                .map(|(_, fingerprint)| *fingerprint),
+            None => {
+                Some(Fingerprint::combine(fingerprints.iter().map(
+                    |(mod_name, fingerprint)| (mod_name.as_str(), *fingerprint),
+                )))
+            }
+        };
+        Ok(fingerprint.map(|fingerprint| fingerprint.to_string()))
+    })?;
+    api.set("fingerprint", fingerprint)?;
+
+    Ok(api)
+}
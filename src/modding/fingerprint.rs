@@ -0,0 +1,83 @@
+//! Hashes identifying the content of mods
+
+use super::ModSource;
+use std::{fmt, io};
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// 64-bit FNV-1a hash.
+///
+/// Written out here rather than using `std`'s hasher, whose output
+/// may change between Rust releases, so fingerprints stay comparable
+/// across builds.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Self {
+        Fnv1a(FNV_OFFSET_BASIS)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    /// Writes the length before the bytes, so that consecutive
+    /// fields can't run into each other.
+    fn write_field(&mut self, bytes: &[u8]) {
+        self.write(&(bytes.len() as u64).to_le_bytes());
+        self.write(bytes);
+    }
+}
+
+/// Hash of the content of a mod, or of a set of mods.
+///
+/// Shown as 16 hexadecimal digits, for bug reports and replays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Fingerprint(u64);
+
+impl Fingerprint {
+    /// Hashes every file of a mod, including the manifest, by path and
+    /// contents.
+    ///
+    /// The result doesn't depend on where the mod is installed, or on
+    /// whether it's a directory or an archive.
+    pub fn of_source(source: &ModSource) -> io::Result<Self> {
+        let mut files = source.files()?;
+        files.sort();
+
+        let mut hasher = Fnv1a::new();
+        for file in &files {
+            hasher.write_field(file.as_bytes());
+            hasher.write_field(&source.read(file)?);
+        }
+
+        Ok(Fingerprint(hasher.0))
+    }
+
+    /// Combines the fingerprints of mods, in load order.
+    ///
+    /// The names are part of the hash, and so is the order, as mods
+    /// loaded in a different order may behave differently.
+    pub fn combine<'a, I>(mods: I) -> Self
+    where
+        I: IntoIterator<Item = (&'a str, Fingerprint)>,
+    {
+        let mut hasher = Fnv1a::new();
+        for (name, fingerprint) in mods {
+            hasher.write_field(name.as_bytes());
+            hasher.write(&fingerprint.0.to_le_bytes());
+        }
+
+        Fingerprint(hasher.0)
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
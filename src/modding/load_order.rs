@@ -0,0 +1,533 @@
+//! Dependency ordering of mods
+
+use super::{Mod, ModList};
+use semver::{Version, VersionReq};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet, BinaryHeap},
+    error::Error,
+    fmt,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DependencyError {
+    /// A mod depends on a mod that was not found.
+    Missing {
+        mod_name: String,
+        dependency: String,
+    },
+    /// A mod depends on a mod that is disabled.
+    Disabled {
+        mod_name: String,
+        dependency: String,
+    },
+    /// A mod depends on a mod that could not be loaded itself.
+    Unavailable {
+        mod_name: String,
+        dependency: String,
+    },
+    /// A mod requires a version of another mod that was not found.
+    Version {
+        mod_name: String,
+        dependency: String,
+        requirement: VersionReq,
+        version: Version,
+    },
+    /// A mod targets a version of the scripting API that the engine
+    /// does not provide.
+    Api {
+        mod_name: String,
+        requirement: VersionReq,
+        api_version: Version,
+    },
+    /// Mods that depend on each other, directly or indirectly.
+    Cycle { mods: Vec<String> },
+}
+
+impl fmt::Display for DependencyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DependencyError::Missing {
+                mod_name,
+                dependency,
+            } => write!(
+                f,
+                "mod '{}' depends on '{}', which was not found",
+                mod_name, dependency
+            ),
+            DependencyError::Disabled {
+                mod_name,
+                dependency,
+            } => write!(
+                f,
+                "mod '{}' depends on '{}', which is disabled",
+                mod_name, dependency
+            ),
+            DependencyError::Unavailable {
+                mod_name,
+                dependency,
+            } => write!(
+                f,
+                "mod '{}' depends on '{}', which could not be loaded",
+                mod_name, dependency
+            ),
+            DependencyError::Version {
+                mod_name,
+                dependency,
+                requirement,
+                version,
+            } => write!(
+                f,
+                "mod '{}' requires '{}' {}, but version {} was found",
+                mod_name, dependency, requirement, version
+            ),
+            DependencyError::Api {
+                mod_name,
+                requirement,
+                api_version,
+            } => write!(
+                f,
+                "mod '{}' requires scripting API {}, but the engine provides {}",
+                mod_name, requirement, api_version
+            ),
+            DependencyError::Cycle { mods } => {
+                write!(f, "dependency cycle between mods '{}'", mods.join("', '"))
+            }
+        }
+    }
+}
+
+impl Error for DependencyError {}
+
+/// Sorts mods so that every mod comes after its dependencies.
+///
+/// When more than one mod is ready to load, the one with the lowest
+/// priority goes first, so that mods with a higher priority are loaded
+/// later and take precedence. Remaining ties are broken by position
+/// in the mod list, and then by name.
+///
+/// Disabled mods, mods that are incompatible with the scripting API
+/// or with the versions of their dependencies, and mods involved in
+/// errors, are left out of the returned order.
+pub fn resolve_load_order(
+    mods: &BTreeMap<String, Mod>,
+    mod_list: &ModList,
+    api_version: &Version,
+) -> (Vec<String>, Vec<DependencyError>) {
+    let mut errors = Vec::new();
+
+    // Disabled mods, incompatible mods, and mods with dependencies
+    // that were not discovered or are disabled, can never load.
+    let mut excluded: BTreeSet<&str> = mods
+        .keys()
+        .filter(|name| !mod_list.is_enabled(name))
+        .map(String::as_str)
+        .collect();
+    for (name, m) in mods {
+        if excluded.contains(name.as_str()) {
+            continue;
+        }
+
+        if let Some(requirement) = &m.meta().api_version {
+            if !requirement.matches(api_version) {
+                errors.push(DependencyError::Api {
+                    mod_name: name.clone(),
+                    requirement: requirement.clone(),
+                    api_version: api_version.clone(),
+                });
+                excluded.insert(name.as_str());
+            }
+        }
+
+        for (dependency, requirement) in &m.meta().requires {
+            if let Some(other) = mods.get(dependency) {
+                if !requirement.matches(&other.meta().version) {
+                    errors.push(DependencyError::Version {
+                        mod_name: name.clone(),
+                        dependency: dependency.clone(),
+                        requirement: requirement.clone(),
+                        version: other.meta().version.clone(),
+                    });
+                    excluded.insert(name.as_str());
+                }
+            }
+        }
+
+        for dependency in &m.meta().dependencies {
+            if !mods.contains_key(dependency) {
+                errors.push(DependencyError::Missing {
+                    mod_name: name.clone(),
+                    dependency: dependency.clone(),
+                });
+                excluded.insert(name.as_str());
+            } else if !mod_list.is_enabled(dependency) {
+                errors.push(DependencyError::Disabled {
+                    mod_name: name.clone(),
+                    dependency: dependency.clone(),
+                });
+                excluded.insert(name.as_str());
+            }
+        }
+    }
+
+    // Number of unloaded dependencies for each mod.
+    let mut pending: BTreeMap<&str, usize> = BTreeMap::new();
+    // Reverse edges, from dependency to dependents.
+    let mut dependents: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for (name, m) in mods {
+        if excluded.contains(name.as_str()) {
+            continue;
+        }
+
+        let dependencies: BTreeSet<&str> =
+            m.meta().dependencies.iter().map(String::as_str).collect();
+        pending.insert(name, dependencies.len());
+        for dependency in dependencies {
+            dependents.entry(dependency).or_default().push(name);
+        }
+    }
+
+    let sort_key = |name: &str| (mods[name].priority(), mod_list.position(name));
+
+    let mut ready: BinaryHeap<Reverse<((u16, usize), &str)>> = pending
+        .iter()
+        .filter(|(_, count)| **count == 0)
+        .map(|(name, _)| Reverse((sort_key(name), *name)))
+        .collect();
+
+    let mut order = Vec::new();
+    while let Some(Reverse((_, name))) = ready.pop() {
+        order.push(name.to_owned());
+        pending.remove(name);
+
+        for dependent in dependents.get(name).into_iter().flatten() {
+            if let Some(count) = pending.get_mut(dependent) {
+                *count -= 1;
+                if *count == 0 {
+                    ready.push(Reverse((sort_key(dependent), *dependent)));
+                }
+            }
+        }
+    }
+
+    // Whatever is still pending is part of a cycle, or depends on
+    // a mod that was excluded.
+    let cycles = find_cycles(mods, &pending.keys().cloned().collect());
+    let in_cycle: BTreeSet<&str> = cycles.iter().flatten().map(String::as_str).collect();
+    for name in pending.keys().filter(|name| !in_cycle.contains(*name)) {
+        let dependency = mods[*name].meta().dependencies.iter().find(|dependency| {
+            pending.contains_key(dependency.as_str()) || excluded.contains(dependency.as_str())
+        });
+
+        if let Some(dependency) = dependency {
+            errors.push(DependencyError::Unavailable {
+                mod_name: (*name).to_owned(),
+                dependency: dependency.clone(),
+            });
+        }
+    }
+    errors.extend(
+        cycles
+            .into_iter()
+            .map(|mods| DependencyError::Cycle { mods }),
+    );
+
+    (order, errors)
+}
+
+/// Finds the strongly connected components of the dependency graph,
+/// restricted to the given mods, that form cycles.
+///
+/// Uses Tarjan's algorithm.
+fn find_cycles(mods: &BTreeMap<String, Mod>, remaining: &BTreeSet<&str>) -> Vec<Vec<String>> {
+    struct Tarjan<'a> {
+        mods: &'a BTreeMap<String, Mod>,
+        remaining: &'a BTreeSet<&'a str>,
+        index: usize,
+        indices: BTreeMap<&'a str, usize>,
+        low_links: BTreeMap<&'a str, usize>,
+        stack: Vec<&'a str>,
+        on_stack: BTreeSet<&'a str>,
+        cycles: Vec<Vec<String>>,
+    }
+
+    impl<'a> Tarjan<'a> {
+        fn visit(&mut self, name: &'a str) {
+            self.indices.insert(name, self.index);
+            self.low_links.insert(name, self.index);
+            self.index += 1;
+            self.stack.push(name);
+            self.on_stack.insert(name);
+
+            let mods = self.mods;
+            for dependency in &mods[name].meta().dependencies {
+                let dependency = dependency.as_str();
+                if !self.remaining.contains(dependency) {
+                    continue;
+                }
+
+                if !self.indices.contains_key(dependency) {
+                    self.visit(dependency);
+                    let low_link = self.low_links[name].min(self.low_links[dependency]);
+                    self.low_links.insert(name, low_link);
+                } else if self.on_stack.contains(dependency) {
+                    let low_link = self.low_links[name].min(self.indices[dependency]);
+                    self.low_links.insert(name, low_link);
+                }
+            }
+
+            if self.low_links[name] == self.indices[name] {
+                let mut component = Vec::new();
+                while let Some(member) = self.stack.pop() {
+                    self.on_stack.remove(member);
+                    component.push(member.to_owned());
+                    if member == name {
+                        break;
+                    }
+                }
+
+                let self_dependent = mods[name].meta().dependencies.iter().any(|d| d == name);
+                if component.len() > 1 || self_dependent {
+                    component.sort();
+                    self.cycles.push(component);
+                }
+            }
+        }
+    }
+
+    let mut tarjan = Tarjan {
+        mods,
+        remaining,
+        index: 0,
+        indices: BTreeMap::new(),
+        low_links: BTreeMap::new(),
+        stack: Vec::new(),
+        on_stack: BTreeSet::new(),
+        cycles: Vec::new(),
+    };
+
+    for name in remaining {
+        if !tarjan.indices.contains_key(name) {
+            tarjan.visit(name);
+        }
+    }
+
+    tarjan.cycles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modding::{Fingerprint, ModMeta, ModSource, ModState};
+    use std::path::PathBuf;
+
+    fn api_version() -> Version {
+        Version::new(0, 1, 0)
+    }
+
+    fn meta(name: &str, dependencies: &[&str]) -> ModMeta {
+        let mut meta = ModMeta::with_name(name);
+        meta.dependencies = dependencies.iter().map(|d| (*d).to_owned()).collect();
+        meta
+    }
+
+    fn mods<I>(metas: I) -> BTreeMap<String, Mod>
+    where
+        I: IntoIterator<Item = ModMeta>,
+    {
+        metas
+            .into_iter()
+            .map(|meta| {
+                let m = Mod {
+                    source: ModSource::Directory(PathBuf::from(&meta.name)),
+                    meta,
+                    entry_point: "main.lua".to_owned(),
+                    environment: None,
+                    state: ModState::Unloaded,
+                    generation: 0,
+                    modified: BTreeMap::new(),
+                    settings: BTreeMap::new(),
+                    settings_table: None,
+                    fingerprint: Fingerprint::combine(None),
+                };
+                (m.name().to_owned(), m)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn dependencies_load_first() {
+        let mods = mods(vec![meta("a", &["b"]), meta("b", &["c"]), meta("c", &[])]);
+
+        let (order, errors) = resolve_load_order(&mods, &ModList::new(), &api_version());
+
+        assert_eq!(order, ["c", "b", "a"]);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn ties_are_broken_by_priority_then_mod_list_then_name() {
+        let mut high = meta("high", &[]);
+        high.priority = 5;
+        let mods = mods(vec![
+            high,
+            meta("listed_last", &[]),
+            meta("listed_first", &[]),
+            meta("unlisted_b", &[]),
+            meta("unlisted_a", &[]),
+        ]);
+        let mut mod_list = ModList::new();
+        mod_list.extend(vec!["high", "listed_first", "listed_last"]);
+
+        let (order, errors) = resolve_load_order(&mods, &mod_list, &api_version());
+
+        assert_eq!(
+            order,
+            [
+                "listed_first",
+                "listed_last",
+                "unlisted_a",
+                "unlisted_b",
+                "high"
+            ]
+        );
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn self_dependency_is_a_cycle() {
+        let mods = mods(vec![meta("a", &["a"]), meta("b", &[])]);
+
+        let (order, errors) = resolve_load_order(&mods, &ModList::new(), &api_version());
+
+        assert_eq!(order, ["b"]);
+        assert_eq!(
+            errors,
+            [DependencyError::Cycle {
+                mods: vec!["a".to_owned()]
+            }]
+        );
+    }
+
+    #[test]
+    fn cycle_excludes_its_dependents() {
+        let mods = mods(vec![
+            meta("a", &["b"]),
+            meta("b", &["a"]),
+            meta("c", &["a"]),
+            meta("d", &[]),
+        ]);
+
+        let (order, errors) = resolve_load_order(&mods, &ModList::new(), &api_version());
+
+        assert_eq!(order, ["d"]);
+        assert_eq!(
+            errors,
+            [
+                DependencyError::Unavailable {
+                    mod_name: "c".to_owned(),
+                    dependency: "a".to_owned(),
+                },
+                DependencyError::Cycle {
+                    mods: vec!["a".to_owned(), "b".to_owned()]
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_dependency_excludes_dependents() {
+        let mods = mods(vec![meta("a", &["missing"]), meta("b", &["a"])]);
+
+        let (order, errors) = resolve_load_order(&mods, &ModList::new(), &api_version());
+
+        assert!(order.is_empty());
+        assert_eq!(
+            errors,
+            [
+                DependencyError::Missing {
+                    mod_name: "a".to_owned(),
+                    dependency: "missing".to_owned(),
+                },
+                DependencyError::Unavailable {
+                    mod_name: "b".to_owned(),
+                    dependency: "a".to_owned(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn disabled_dependency_is_reported() {
+        let mods = mods(vec![meta("a", &["b"]), meta("b", &[]), meta("c", &[])]);
+        let mut mod_list = ModList::new();
+        mod_list.set_enabled("b", false);
+
+        let (order, errors) = resolve_load_order(&mods, &mod_list, &api_version());
+
+        assert_eq!(order, ["c"]);
+        assert_eq!(
+            errors,
+            [DependencyError::Disabled {
+                mod_name: "a".to_owned(),
+                dependency: "b".to_owned(),
+            }]
+        );
+    }
+
+    #[test]
+    fn required_versions_must_match() {
+        let mut core = meta("core", &[]);
+        core.version = Version::new(0, 2, 3);
+        let mut compatible = meta("compatible", &["core"]);
+        compatible
+            .requires
+            .insert("core".to_owned(), VersionReq::parse(">=0.2, <0.3").unwrap());
+        let mut outdated = meta("outdated", &["core"]);
+        outdated
+            .requires
+            .insert("core".to_owned(), VersionReq::parse("^0.1").unwrap());
+        let mods = mods(vec![core, compatible, outdated]);
+
+        let (order, errors) = resolve_load_order(&mods, &ModList::new(), &api_version());
+
+        assert_eq!(order, ["core", "compatible"]);
+        assert_eq!(
+            errors,
+            [DependencyError::Version {
+                mod_name: "outdated".to_owned(),
+                dependency: "core".to_owned(),
+                requirement: VersionReq::parse("^0.1").unwrap(),
+                version: Version::new(0, 2, 3),
+            }]
+        );
+    }
+
+    #[test]
+    fn api_version_must_match() {
+        let mut current = meta("current", &[]);
+        current.api_version = Some(VersionReq::parse("0.1").unwrap());
+        let mut newer = meta("newer", &[]);
+        newer.api_version = Some(VersionReq::parse("0.2").unwrap());
+        let dependent = meta("dependent", &["newer"]);
+        let mods = mods(vec![current, newer, dependent]);
+
+        let (order, errors) = resolve_load_order(&mods, &ModList::new(), &Version::new(0, 1, 4));
+
+        assert_eq!(order, ["current"]);
+        assert_eq!(
+            errors,
+            [
+                DependencyError::Api {
+                    mod_name: "newer".to_owned(),
+                    requirement: VersionReq::parse("0.2").unwrap(),
+                    api_version: Version::new(0, 1, 4),
+                },
+                DependencyError::Unavailable {
+                    mod_name: "dependent".to_owned(),
+                    dependency: "newer".to_owned(),
+                },
+            ]
+        );
+    }
+}
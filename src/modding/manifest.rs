@@ -0,0 +1,229 @@
+//! Mod manifest
+
+use super::{Capability, ModSource, SettingError, SettingSchema};
+use semver::{Version, VersionReq};
+use serde::Deserialize;
+use std::{collections::BTreeMap, error::Error, fmt, io, path::PathBuf};
+
+/// Description of a mod, read from the manifest file in the mod's root.
+///
+/// ```toml
+/// name = "example"
+/// version = "0.1.0"
+/// authors = ["Someone <someone@example.com>"]
+/// description = "Example mod"
+/// entry_point = "main.lua"
+/// dependencies = ["core"]
+/// requires = { core = ">=0.2, <0.3", othermod = "^1.1" }
+/// api_version = "0.1"
+/// capabilities = ["fs_read", "os_time"]
+/// priority = 10
+///
+/// [provides]
+/// events = ["door_opened"]
+/// prefabs = ["door"]
+/// components = ["Hinge"]
+///
+/// [settings.speed]
+/// type = "number"
+/// min = 0
+/// default = 1
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModMeta {
+    /// Unique name of the mod, which can't be empty or contain `/`,
+    /// `\`, `:` or `..`.
+    pub name: String,
+    #[serde(default = "default_version")]
+    pub version: Version,
+    #[serde(default)]
+    pub authors: Vec<String>,
+    #[serde(default)]
+    pub description: String,
+    /// Overrides the entry point script from the mod settings.
+    #[serde(default)]
+    pub entry_point: Option<String>,
+    /// Names of mods that must be loaded before this one, including
+    /// the mods listed in `requires`.
+    #[serde(default)]
+    pub dependencies: Vec<String>,
+    /// Versions of other mods that this mod works with.
+    #[serde(default)]
+    pub requires: BTreeMap<String, VersionReq>,
+    /// Versions of the engine's scripting API that this mod works
+    /// with. A plain version such as `"0.1"` accepts any compatible
+    /// release, following Cargo's rules.
+    #[serde(default)]
+    pub api_version: Option<VersionReq>,
+    /// Parts of the standard library the mod may use, beyond the ones
+    /// every mod can use.
+    #[serde(default)]
+    pub capabilities: Vec<Capability>,
+    #[serde(default)]
+    pub priority: u16,
+    /// Names the mod registers, which other mods should not reuse.
+    #[serde(default)]
+    pub provides: Provides,
+    /// Options the user can change, by name.
+    #[serde(default)]
+    pub settings: BTreeMap<String, SettingSchema>,
+}
+
+/// Names declared by a mod, checked for conflicts with other mods.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Provides {
+    #[serde(default)]
+    pub events: Vec<String>,
+    #[serde(default)]
+    pub prefabs: Vec<String>,
+    #[serde(default)]
+    pub components: Vec<String>,
+}
+
+fn default_version() -> Version {
+    Version::new(0, 0, 0)
+}
+
+impl ModMeta {
+    /// Creates the metadata for a mod without a manifest.
+    pub fn with_name<S>(name: S) -> Self
+    where
+        S: Into<String>,
+    {
+        ModMeta {
+            name: name.into(),
+            version: default_version(),
+            authors: Vec::new(),
+            description: String::new(),
+            entry_point: None,
+            dependencies: Vec::new(),
+            requires: BTreeMap::new(),
+            api_version: None,
+            capabilities: Vec::new(),
+            priority: 0,
+            provides: Provides::default(),
+            settings: BTreeMap::new(),
+        }
+    }
+
+    /// Reads and parses the manifest file of a mod.
+    pub fn from_source(source: &ModSource, file_name: &str) -> Result<Self, ManifestError> {
+        let path = source.path().join(file_name);
+        let contents = source
+            .read(file_name)
+            .and_then(|bytes| {
+                String::from_utf8(bytes)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+            })
+            .map_err(|err| ManifestError {
+                path: path.clone(),
+                kind: ManifestErrorKind::Io(err),
+            })?;
+
+        let mut meta: ModMeta = toml::from_str(&contents).map_err(|err| ManifestError {
+            path: path.clone(),
+            kind: ManifestErrorKind::Parse(err),
+        })?;
+        check_name(&meta.name).map_err(|message| ManifestError {
+            path: path.clone(),
+            kind: ManifestErrorKind::Name(message),
+        })?;
+
+        for (name, setting) in &meta.settings {
+            if let Err(message) = setting.validate(&setting.default_value()) {
+                return Err(ManifestError {
+                    path,
+                    kind: ManifestErrorKind::Setting(SettingError {
+                        mod_name: meta.name.clone(),
+                        setting: name.clone(),
+                        message,
+                    }),
+                });
+            }
+        }
+
+        for name in meta.requires.keys() {
+            if !meta.dependencies.contains(name) {
+                meta.dependencies.push(name.clone());
+            }
+        }
+
+        Ok(meta)
+    }
+}
+
+/// Checks that a mod name can be used as a directory name, and in the
+/// `mod:module` paths of `require`.
+pub fn check_name(name: &str) -> Result<(), String> {
+    if name.is_empty() {
+        Err("mod name is empty".to_owned())
+    } else if name.contains(['/', '\\', ':']) {
+        Err(format!("mod name '{}' contains '/', '\\' or ':'", name))
+    } else if name == "." || name.contains("..") {
+        Err(format!("mod name '{}' contains '..' or is '.'", name))
+    } else {
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct ManifestError {
+    /// Location of the offending manifest.
+    pub path: PathBuf,
+    pub kind: ManifestErrorKind,
+}
+
+#[derive(Debug)]
+pub enum ManifestErrorKind {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    /// The name of the mod can't be used.
+    Name(String),
+    /// The default of a declared setting is not allowed by the
+    /// setting itself.
+    Setting(SettingError),
+}
+
+impl fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            ManifestErrorKind::Io(err) => write!(
+                f,
+                "failed reading manifest '{}': {}",
+                self.path.to_string_lossy(),
+                err
+            ),
+            ManifestErrorKind::Parse(err) => write!(
+                f,
+                "invalid manifest '{}': {}",
+                self.path.to_string_lossy(),
+                err
+            ),
+            ManifestErrorKind::Name(message) => write!(
+                f,
+                "invalid manifest '{}': {}",
+                self.path.to_string_lossy(),
+                message
+            ),
+            ManifestErrorKind::Setting(err) => write!(
+                f,
+                "invalid manifest '{}': {}",
+                self.path.to_string_lossy(),
+                err
+            ),
+        }
+    }
+}
+
+impl Error for ManifestError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match &self.kind {
+            ManifestErrorKind::Io(err) => Some(err),
+            ManifestErrorKind::Parse(err) => Some(err),
+            ManifestErrorKind::Setting(err) => Some(err),
+            ManifestErrorKind::Name(_) => None,
+        }
+    }
+}
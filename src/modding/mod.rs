@@ -0,0 +1,1520 @@
+//! Modding and scripting
+
+mod budget;
+mod capabilities;
+mod conflicts;
+mod environment;
+mod error;
+mod events;
+mod exports;
+mod fingerprint;
+mod load_order;
+mod manifest;
+mod mod_list;
+mod require;
+mod runner;
+mod settings;
+mod source;
+mod storage;
+mod value;
+mod vfs;
+
+pub use budget::{BudgetExceeded, BudgetMeter};
+pub use capabilities::Capability;
+pub use conflicts::{Conflict, ConflictKind, ConflictPolicy, NameRegistry};
+pub use error::{LoadReport, ModLoadError};
+pub use events::{CallbackFailure, EventBus};
+pub use fingerprint::Fingerprint;
+pub use load_order::DependencyError;
+pub use manifest::{ManifestError, ModMeta};
+pub use mod_list::ModList;
+pub use runner::ModRunner;
+pub use settings::{SettingError, SettingSchema, SettingValue, UserSettings};
+pub use source::ModSource;
+pub use storage::Storage;
+pub use value::ScriptValue;
+pub use vfs::Vfs;
+
+use rlua::{Context, Function, Lua, RegistryKey, Table, ToLuaMulti, Value};
+use semver::Version;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env, fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Version of the scripting API offered to mods.
+///
+/// Bump the minor version whenever the Lua API changes in a way that
+/// breaks existing mods, while it is below 1.0.
+pub const API_VERSION: &str = "0.1.0";
+
+/// Functions that the engine calls in every mod, which are not
+/// checked for conflicts.
+const HOOKS: &[&str] = &[
+    "on_load",
+    "on_enable",
+    "on_init",
+    "on_update",
+    "on_reload",
+    "on_setting_changed",
+    "on_disable",
+    "on_unload",
+    "on_shutdown",
+];
+
+/// Globals that the hub sets in the environment of every mod.
+const MOD_API: &[&str] = &["events", "mods", "require", "settings", "storage"];
+
+const DEFAULT_ENTRY_POINT: &str = "init.lua";
+const DEFAULT_MANIFEST: &str = "mod.toml";
+const DEFAULT_DIRECTORY_NAME: &str = "mods";
+const DEFAULT_MOD_LIST: &str = "modlist.toml";
+const DEFAULT_DATA_DIRECTORY_NAME: &str = "mod_data";
+const DEFAULT_USER_SETTINGS: &str = "mod_settings.toml";
+const DEFAULT_RELOAD_INTERVAL_SECS: u64 = 1;
+const DEFAULT_INSTRUCTION_BUDGET: u64 = 10_000_000;
+const DEFAULT_MEMORY_BUDGET: usize = 64 * 1024 * 1024;
+const DEFAULT_MEMORY_LIMIT: usize = 512 * 1024 * 1024;
+const DEFAULT_BUDGET_CHECK_INTERVAL: u32 = 1000;
+
+pub struct ModHub {
+    mods: BTreeMap<String, Mod>,
+    /// Names of mods that can be loaded, with dependencies first.
+    load_order: Vec<String>,
+    /// Problems found while discovering and loading mods.
+    report: LoadReport,
+    /// Which mod registered each name, and the conflicts between them,
+    /// shared with the runner for names registered by scripts.
+    names: Arc<Mutex<NameRegistry>>,
+    budget: BudgetMeter,
+    /// Calls into mods for the script systems.
+    runner: ModRunner,
+    mod_list: ModList,
+    /// Values chosen by the user for the settings declared by mods.
+    user_settings: UserSettings,
+    /// When the user settings file was last modified, as of reading it.
+    user_settings_modified: Option<SystemTime>,
+    events: Arc<Mutex<EventBus>>,
+    /// Files of the mods in the load order.
+    vfs: Arc<Vfs>,
+    resolver: Arc<require::ModuleResolver>,
+    /// Fingerprint of each mod in the load order, shared with the
+    /// `mods` table of the scripts.
+    fingerprints: Arc<Mutex<Vec<(String, Fingerprint)>>>,
+    /// Number of times mod scripts have been executed, used to tell
+    /// versions of a mod's scripts apart.
+    generations: u32,
+    /// When mod files were last checked for changes.
+    last_poll: Instant,
+    settings: ModSettings,
+}
+
+impl ModHub {
+    pub fn new() -> Self {
+        Self::with_directory(env::current_dir().unwrap())
+    }
+
+    /// Creates a hub for the mods, mod list and mod data in the given
+    /// directory.
+    pub fn with_directory<P>(directory: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        let directory = directory.as_ref();
+        let mod_directory_path = directory.join(DEFAULT_DIRECTORY_NAME);
+        let mod_list_path = directory.join(DEFAULT_MOD_LIST);
+        let data_directory_path = directory.join(DEFAULT_DATA_DIRECTORY_NAME);
+        let user_settings_path = directory.join(DEFAULT_USER_SETTINGS);
+        let budget = BudgetMeter::new(
+            DEFAULT_INSTRUCTION_BUDGET,
+            DEFAULT_MEMORY_BUDGET,
+            DEFAULT_BUDGET_CHECK_INTERVAL,
+        );
+
+        let settings = ModSettings {
+            entry_point: DEFAULT_ENTRY_POINT.to_owned(),
+            manifest: DEFAULT_MANIFEST.to_owned(),
+            directory_path: mod_directory_path,
+            mod_list_path,
+            data_directory_path,
+            user_settings_path,
+            reload_interval: Duration::from_secs(DEFAULT_RELOAD_INTERVAL_SECS),
+            conflict_policies: [
+                (ConflictKind::GlobalFunction, ConflictPolicy::Warn),
+                (ConflictKind::Event, ConflictPolicy::Warn),
+                (ConflictKind::Prefab, ConflictPolicy::Error),
+                (ConflictKind::Component, ConflictPolicy::Error),
+                (ConflictKind::Asset, ConflictPolicy::PriorityWins),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+            instruction_budget: DEFAULT_INSTRUCTION_BUDGET,
+            memory_budget: DEFAULT_MEMORY_BUDGET,
+            memory_limit: Some(DEFAULT_MEMORY_LIMIT),
+            budget_check_interval: DEFAULT_BUDGET_CHECK_INTERVAL,
+        };
+        let names = Arc::new(Mutex::new(NameRegistry::new()));
+
+        ModHub {
+            mods: BTreeMap::new(),
+            load_order: Vec::new(),
+            report: LoadReport::new(),
+            names: names.clone(),
+            budget: budget.clone(),
+            runner: ModRunner::new(budget, names, settings.conflict_policies.clone()),
+            mod_list: ModList::new(),
+            user_settings: UserSettings::new(),
+            user_settings_modified: None,
+            events: Arc::new(Mutex::new(EventBus::new())),
+            vfs: Arc::new(Vfs::new()),
+            resolver: Arc::new(require::ModuleResolver::new(Arc::new(Vfs::new()))),
+            fingerprints: Arc::new(Mutex::new(Vec::new())),
+            generations: 0,
+            last_poll: Instant::now(),
+            settings,
+        }
+    }
+
+    pub fn settings(&self) -> &ModSettings {
+        &self.settings
+    }
+
+    /// Iterates all registered mods, ordered by name.
+    pub fn mods(&self) -> impl Iterator<Item = &Mod> {
+        self.mods.values()
+    }
+
+    /// Iterates the mods that can be loaded, with every mod
+    /// following its dependencies.
+    pub fn load_order(&self) -> impl Iterator<Item = &Mod> {
+        self.load_order.iter().map(move |name| &self.mods[name])
+    }
+
+    /// Fingerprint of the content of all mods in the load order.
+    ///
+    /// Two runs with the same fingerprint load the same files of the
+    /// same mods, in the same order.
+    pub fn fingerprint(&self) -> Fingerprint {
+        Fingerprint::combine(self.load_order().map(|m| (m.name(), m.fingerprint)))
+    }
+
+    /// Version of the scripts of each enabled mod, in load order.
+    ///
+    /// Changes whenever a mod is enabled, disabled or suspended, or its
+    /// scripts are executed again, so the host knows when to collect
+    /// what the mods declared once more.
+    pub fn enabled_scripts(&self) -> Vec<(String, u32)> {
+        self.load_order()
+            .filter(|m| m.state == ModState::Enabled)
+            .map(|m| (m.name().to_owned(), m.generation))
+            .collect()
+    }
+
+    /// Shares the fingerprints of the mods with their scripts, after
+    /// the load order or the content of a mod changed.
+    fn update_fingerprints(&self) {
+        *self.fingerprints.lock().unwrap() = self
+            .load_order()
+            .map(|m| (m.name().to_owned(), m.fingerprint))
+            .collect();
+    }
+
+    /// Enables a mod in the mod list.
+    ///
+    /// A loaded mod is enabled right away, once its dependencies are
+    /// enabled, along with the mods depending on it. Other mods are
+    /// enabled the next time mods are discovered. Returns false if no
+    /// mod with the name was discovered.
+    pub fn enable_mod(&mut self, lua_ctx: Context, name: &str) -> rlua::Result<bool> {
+        self.set_mod_enabled(lua_ctx, name, true)
+    }
+
+    /// Disables a mod in the mod list.
+    ///
+    /// A loaded mod is disabled right away, after the mods depending
+    /// on it. Returns false if no mod with the name was discovered.
+    pub fn disable_mod(&mut self, lua_ctx: Context, name: &str) -> rlua::Result<bool> {
+        self.set_mod_enabled(lua_ctx, name, false)
+    }
+
+    fn set_mod_enabled(
+        &mut self,
+        lua_ctx: Context,
+        name: &str,
+        enabled: bool,
+    ) -> rlua::Result<bool> {
+        if self.mods.contains_key(name) {
+            self.mod_list.set_enabled(name, enabled);
+            self.update_enabled(lua_ctx)?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Enables and disables loaded mods to match the mod list.
+    ///
+    /// A mod can only be enabled when all its dependencies are. Mods
+    /// are disabled in reverse load order, calling their `on_disable`
+    /// hooks, and then enabled in load order, calling their
+    /// `on_enable` hooks. Only enabled mods receive hooks and events.
+    fn update_enabled(&mut self, lua_ctx: Context) -> rlua::Result<()> {
+        let mut enabled = BTreeSet::new();
+        for name in &self.load_order {
+            let m = &self.mods[name];
+            if m.state != ModState::Unloaded
+                && self.mod_list.is_enabled(name)
+                && m.meta.dependencies.iter().all(|d| enabled.contains(d))
+            {
+                enabled.insert(name.clone());
+            }
+        }
+
+        for name in self.load_order.clone().iter().rev() {
+            if self.mods[name].state == ModState::Enabled && !enabled.contains(name) {
+                self.run_lifecycle_hook(lua_ctx, name, "on_disable")?;
+                self.mods.get_mut(name).unwrap().state = ModState::Loaded;
+                self.events.lock().unwrap().set_muted(name, true);
+            }
+        }
+
+        for name in self.load_order.clone() {
+            if self.mods[&name].state == ModState::Loaded && enabled.contains(&name) {
+                self.mods.get_mut(&name).unwrap().state = ModState::Enabled;
+                self.events.lock().unwrap().set_muted(&name, false);
+                self.run_lifecycle_hook(lua_ctx, &name, "on_enable")?;
+            }
+        }
+        self.update_runner();
+
+        Ok(())
+    }
+
+    /// Writes the mod list to its file.
+    pub fn save_mod_list(&self) -> io::Result<()> {
+        self.mod_list.save(&self.settings.mod_list_path)
+    }
+
+    /// Changes a setting of a mod, and calls the mod's optional
+    /// `on_setting_changed` hook with the name, new value and previous
+    /// value of the setting. Errors raised by the hook are added to the
+    /// report.
+    ///
+    /// The value must be allowed by the setting's declaration. It is
+    /// recorded in the user settings, which can then be saved.
+    ///
+    /// Changes to the user settings file are applied the same way when
+    /// mods are checked for changes.
+    pub fn set_setting(
+        &mut self,
+        lua_ctx: Context,
+        mod_name: &str,
+        setting: &str,
+        value: SettingValue,
+    ) -> rlua::Result<()> {
+        let error = |message: &str| {
+            rlua::Error::external(SettingError {
+                mod_name: mod_name.to_owned(),
+                setting: setting.to_owned(),
+                message: message.to_owned(),
+            })
+        };
+
+        let m = self
+            .mods
+            .get(mod_name)
+            .ok_or_else(|| error("the mod was not found"))?;
+        let schema = m
+            .meta
+            .settings
+            .get(setting)
+            .ok_or_else(|| error("the mod has no such setting"))?;
+        schema.validate(&value).map_err(|message| error(&message))?;
+
+        self.user_settings.set(mod_name, setting, value.clone());
+        self.apply_setting(lua_ctx, mod_name, setting, value)
+    }
+
+    /// Gives a setting of a mod a new value, which must be valid, and
+    /// calls the mod's `on_setting_changed` hook if the value changed.
+    fn apply_setting(
+        &mut self,
+        lua_ctx: Context,
+        mod_name: &str,
+        setting: &str,
+        value: SettingValue,
+    ) -> rlua::Result<()> {
+        let m = self.mods.get_mut(mod_name).expect("mod not registered");
+        let previous = match m.settings.insert(setting.to_owned(), value.clone()) {
+            Some(previous) => previous,
+            None => m.meta.settings[setting].default_value(),
+        };
+        if previous == value {
+            return Ok(());
+        }
+
+        if let Some(key) = &m.settings_table {
+            let table: Table = lua_ctx.registry_value(key)?;
+            table.raw_set(setting, value.clone())?;
+        }
+        if let Some(environment) = m.environment(lua_ctx)? {
+            if let Some(on_setting_changed) =
+                environment.raw_get::<_, Option<Function>>("on_setting_changed")?
+            {
+                let result = self.call_mod_function(
+                    lua_ctx,
+                    mod_name,
+                    on_setting_changed,
+                    (setting, value, previous),
+                );
+                if let Err(err) = result {
+                    self.report_hook_error(mod_name, "on_setting_changed", err);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the user's values for mod settings to their file.
+    pub fn save_user_settings(&mut self) -> io::Result<()> {
+        self.user_settings.save(&self.settings.user_settings_path)?;
+        self.user_settings_modified = modified_time(&self.settings.user_settings_path);
+
+        Ok(())
+    }
+
+    /// Reads the user's values for mod settings from their file.
+    fn load_user_settings(&mut self) {
+        self.user_settings_modified = modified_time(&self.settings.user_settings_path);
+        self.user_settings = match UserSettings::load(&self.settings.user_settings_path) {
+            Ok(user_settings) => user_settings,
+            Err(err) => {
+                eprintln!(
+                    "failed loading mod settings '{}', using defaults: {}",
+                    self.settings.user_settings_path.to_string_lossy(),
+                    err
+                );
+                UserSettings::new()
+            }
+        };
+    }
+
+    /// Reads the user settings file again if it changed, and applies
+    /// the values that changed to the mods in the load order.
+    fn reload_user_settings(&mut self, lua_ctx: Context) -> rlua::Result<()> {
+        if modified_time(&self.settings.user_settings_path) == self.user_settings_modified {
+            return Ok(());
+        }
+
+        println!("Reloading mod settings");
+        self.load_user_settings();
+        for name in self.load_order.clone() {
+            let (values, errors) = self
+                .user_settings
+                .resolve(&name, &self.mods[&name].meta.settings);
+            for err in errors {
+                self.report.push(err);
+            }
+            for (setting, value) in values {
+                self.apply_setting(lua_ctx, &name, &setting, value)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Persistent data of the mod with the given name.
+    pub fn storage(&self, name: &str) -> Storage {
+        Storage::new(self.settings.data_directory_path.join(name))
+    }
+
+    /// Whether a mod that is enabled in the mod list declares the
+    /// `debug` capability, so the host should load the debug library.
+    pub fn needs_debug_library(&self) -> bool {
+        self.load_order().any(|m| {
+            self.mod_list.is_enabled(m.name()) && m.meta.capabilities.contains(&Capability::Debug)
+        })
+    }
+
+    /// Problems found while discovering and loading mods.
+    pub fn report(&self) -> &LoadReport {
+        &self.report
+    }
+
+    /// Adds a problem that the host found with a mod to the report,
+    /// such as a system using a resource the host doesn't provide.
+    pub fn report_error<E>(&mut self, error: E)
+    where
+        E: Into<ModLoadError>,
+    {
+        self.report.push(error);
+    }
+
+    /// Names registered by the loaded mods, and the conflicts found
+    /// between them.
+    pub fn names(&self) -> MutexGuard<'_, NameRegistry> {
+        self.names.lock().unwrap()
+    }
+
+    /// Scans the mods directory and registers every subdirectory or
+    /// zip archive that contains a manifest or an entry point script.
+    ///
+    /// A missing mods directory is not an error; there are simply
+    /// no mods to load. Mods that can't be read, or have an invalid
+    /// manifest or no entry point, are added to the report and
+    /// skipped.
+    ///
+    /// Afterwards the load order is rebuilt from the declared
+    /// dependencies and the mod list. Disabled mods, and mods with
+    /// missing or cyclic dependencies, are left out of the load order.
+    pub fn discover(&mut self) -> io::Result<()> {
+        self.report.clear();
+        self.mod_list = match ModList::load(&self.settings.mod_list_path) {
+            Ok(mod_list) => mod_list,
+            Err(err) => {
+                eprintln!(
+                    "failed loading mod list '{}', enabling all mods: {}",
+                    self.settings.mod_list_path.to_string_lossy(),
+                    err
+                );
+                ModList::new()
+            }
+        };
+
+        if !self.settings.directory_path.is_dir() {
+            println!(
+                "Mod directory '{}' not found",
+                self.settings.directory_path.to_string_lossy()
+            );
+            return Ok(());
+        }
+
+        // Sorted, so that the same mod wins when two share a name.
+        let mut paths = fs::read_dir(&self.settings.directory_path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+        paths.sort();
+
+        for path in paths {
+            if !path.is_dir() && !ModSource::is_archive(&path) {
+                continue;
+            }
+
+            let result = ModSource::open(&path)
+                .map_err(|error| ModLoadError::Io { path, error })
+                .and_then(|source| self.load_mod(source));
+            if let Err(err) = result {
+                self.report.push(err);
+            }
+        }
+
+        self.load_user_settings();
+        for (name, m) in &mut self.mods {
+            let (values, errors) = self.user_settings.resolve(name, &m.meta.settings);
+            m.settings = values;
+            for err in errors {
+                self.report.push(err);
+            }
+        }
+
+        self.mod_list.extend(self.mods.keys().map(String::as_str));
+        self.resolve_load_order();
+
+        Ok(())
+    }
+
+    fn resolve_load_order(&mut self) {
+        let api_version = Version::parse(API_VERSION).expect("invalid scripting API version");
+        let (order, errors) =
+            load_order::resolve_load_order(&self.mods, &self.mod_list, &api_version);
+        for err in errors {
+            self.report.push(err);
+        }
+
+        // Mods whose names conflict are rejected according to the
+        // conflict policies, along with the mods that depend on them.
+        let mut names = self.names.lock().unwrap();
+        *names = NameRegistry::new();
+        let mut load_order: Vec<String> = Vec::new();
+        for name in order {
+            let m = &self.mods[&name];
+
+            // Dependencies come first in the order, so any that are
+            // missing by now have been rejected.
+            if let Some(dependency) = m
+                .meta
+                .dependencies
+                .iter()
+                .find(|dependency| !load_order.contains(dependency))
+            {
+                self.report.push(DependencyError::Unavailable {
+                    mod_name: name.clone(),
+                    dependency: dependency.clone(),
+                });
+                continue;
+            }
+
+            match m.register_names(&mut names, &self.settings) {
+                Ok(()) => load_order.push(name),
+                Err(err) => {
+                    names.unregister_mod(&name);
+                    self.report.push(err);
+                }
+            }
+        }
+        drop(names);
+
+        // Files are supplied by the mod that owns them as assets. When
+        // priority settles asset conflicts, mods are mounted by priority,
+        // so a mod keeps its files even if a mod with a lower priority
+        // loads after it.
+        let mut mounts = load_order.clone();
+        if self.settings.conflict_policy(ConflictKind::Asset) == ConflictPolicy::PriorityWins {
+            mounts.sort_by_key(|name| self.mods[name].priority());
+        }
+        let mut vfs = Vfs::new();
+        for name in &mounts {
+            vfs.mount(name.as_str(), self.mods[name].source.clone());
+        }
+
+        let vfs = Arc::new(vfs);
+        let mut resolver = require::ModuleResolver::new(vfs.clone());
+        for name in &load_order {
+            resolver.add_mod(name.as_str(), self.mods[name].meta.dependencies.clone());
+        }
+
+        self.load_order = load_order;
+        self.vfs = vfs;
+        self.resolver = Arc::new(resolver);
+        self.update_fingerprints();
+        self.update_runner();
+    }
+
+    /// Registers the mod contained in the given directory or archive.
+    ///
+    /// The mod is described by its manifest. Without a manifest the
+    /// mod is named after its directory or archive. Mods with neither
+    /// a manifest nor an entry point are ignored.
+    pub fn load_mod(&mut self, source: ModSource) -> Result<(), ModLoadError> {
+        let meta = if source.is_file(&self.settings.manifest) {
+            ModMeta::from_source(&source, &self.settings.manifest)?
+        } else if source.is_file(&self.settings.entry_point) {
+            let name = source
+                .path()
+                .file_stem()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            manifest::check_name(&name).map_err(|message| ManifestError {
+                path: source.path().to_path_buf(),
+                kind: manifest::ManifestErrorKind::Name(message),
+            })?;
+            ModMeta::with_name(name)
+        } else {
+            return Ok(());
+        };
+
+        if let Some(other) = self.mods.get(&meta.name) {
+            return Err(ModLoadError::Duplicate {
+                mod_name: meta.name,
+                path: source.path().to_path_buf(),
+                other: other.path().to_path_buf(),
+            });
+        }
+
+        let entry_point = meta
+            .entry_point
+            .clone()
+            .unwrap_or_else(|| self.settings.entry_point.clone());
+        if !source.is_file(&entry_point) {
+            return Err(ModLoadError::MissingEntryPoint {
+                mod_name: meta.name,
+                entry_point,
+            });
+        }
+
+        let fingerprint = Fingerprint::of_source(&source).map_err(|error| ModLoadError::Io {
+            path: source.path().to_path_buf(),
+            error,
+        })?;
+
+        println!(
+            "Found mod '{}' {} ({})",
+            meta.name, meta.version, fingerprint
+        );
+        self.mods.insert(
+            meta.name.clone(),
+            Mod {
+                meta,
+                source,
+                entry_point,
+                environment: None,
+                state: ModState::Unloaded,
+                settings: BTreeMap::new(),
+                settings_table: None,
+                generation: 0,
+                modified: BTreeMap::new(),
+                fingerprint,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Executes the entry point of every mod, in load order.
+    ///
+    /// Each mod runs in its own environment, so the globals and
+    /// callbacks of one mod don't clash with those of another. The
+    /// current globals act as the shared API, which mods can read
+    /// but not modify. A mod that fails to load is added to the
+    /// report and skipped.
+    ///
+    /// Once all scripts have run, the `on_load` hook of every loaded
+    /// mod is called in load order, and the mods enabled in the mod
+    /// list are enabled. A mod whose `on_load` hook fails is unloaded
+    /// again.
+    pub fn load_scripts(&mut self, lua_ctx: Context) -> rlua::Result<()> {
+        for name in self.load_order.clone() {
+            if let Err(err) = self.execute_mod(lua_ctx, &name) {
+                self.report.push(err);
+            }
+        }
+
+        for name in self.load_order.clone() {
+            self.call_on_load(lua_ctx, &name)?;
+        }
+        self.update_enabled(lua_ctx)?;
+        self.last_poll = Instant::now();
+
+        Ok(())
+    }
+
+    /// Calls the `on_load` hook of a mod whose scripts were just
+    /// executed for the first time.
+    fn call_on_load(&mut self, lua_ctx: Context, name: &str) -> rlua::Result<()> {
+        if self.mods[name].state != ModState::Unloaded || self.mods[name].environment.is_none() {
+            return Ok(());
+        }
+
+        self.mods.get_mut(name).unwrap().state = ModState::Loaded;
+        if !self.run_lifecycle_hook(lua_ctx, name, "on_load")? {
+            self.drop_environment(lua_ctx, name)?;
+        }
+
+        Ok(())
+    }
+
+    /// Calls the `on_shutdown` hook of every loaded mod in reverse load
+    /// order, and then unloads all mods.
+    ///
+    /// Should be called once, when the application exits.
+    pub fn shutdown(&mut self, lua_ctx: Context) -> rlua::Result<()> {
+        for name in self.load_order.clone().iter().rev() {
+            if self.mods[name].state != ModState::Unloaded {
+                self.run_lifecycle_hook(lua_ctx, name, "on_shutdown")?;
+            }
+        }
+
+        self.unload_scripts(lua_ctx)
+    }
+
+    /// Unloads every mod in reverse load order, calling the
+    /// `on_disable` hook of enabled mods and then the `on_unload` hook.
+    ///
+    /// Mods can be loaded again with `load_scripts`.
+    pub fn unload_scripts(&mut self, lua_ctx: Context) -> rlua::Result<()> {
+        for name in self.load_order.clone().iter().rev() {
+            let state = self.mods[name].state;
+            if state == ModState::Enabled {
+                self.run_lifecycle_hook(lua_ctx, name, "on_disable")?;
+            }
+            if state != ModState::Unloaded {
+                self.run_lifecycle_hook(lua_ctx, name, "on_unload")?;
+            }
+            self.drop_environment(lua_ctx, name)?;
+        }
+        lua_ctx.expire_registry_values();
+
+        Ok(())
+    }
+
+    /// Forgets the environment and scripts of a mod, without calling
+    /// any hooks.
+    fn drop_environment(&mut self, lua_ctx: Context, name: &str) -> rlua::Result<()> {
+        let m = self.mods.get_mut(name).expect("mod not registered");
+
+        let mut events = self.events.lock().unwrap();
+        events.unsubscribe(name, m.generation);
+        events.set_muted(name, false);
+        drop(events);
+
+        require::take_modules(lua_ctx, name)?;
+        require::register_environment(lua_ctx, name, None)?;
+        self.names
+            .lock()
+            .unwrap()
+            .unregister(ConflictKind::GlobalFunction, name);
+        m.environment = None;
+        m.settings_table = None;
+        m.state = ModState::Unloaded;
+        self.update_runner();
+
+        Ok(())
+    }
+
+    /// Calls a lifecycle hook of a mod, if the mod defines it.
+    ///
+    /// Errors raised by the hook are added to the report rather than
+    /// returned, so one broken mod doesn't stop the others. Returns
+    /// whether the hook succeeded.
+    fn run_lifecycle_hook(
+        &mut self,
+        lua_ctx: Context,
+        name: &str,
+        hook: &str,
+    ) -> rlua::Result<bool> {
+        let func = match self.mods[name].environment(lua_ctx)? {
+            Some(environment) => environment.raw_get::<_, Option<Function>>(hook)?,
+            None => None,
+        };
+
+        match func.map(|func| self.call_mod_function(lua_ctx, name, func, ())) {
+            Some(Err(err)) => {
+                self.report_hook_error(name, hook, err);
+                Ok(false)
+            }
+            _ => Ok(true),
+        }
+    }
+
+    fn report_hook_error(&mut self, name: &str, hook: &str, err: rlua::Error) {
+        let err = ModLoadError::from_lua(name, &self.mods[name].entry_point, err);
+        eprintln!("{} failed: {}", hook, err);
+        self.report.push(err);
+    }
+
+    /// Reloads the scripts of mods with files that changed since
+    /// they were last executed.
+    ///
+    /// The file system is polled at most once per reload interval,
+    /// which also applies changes to the user settings file.
+    /// A reloaded mod runs in a new environment, and its optional
+    /// `on_reload` hook is called with the previous environment, so
+    /// the mod can carry over its state. If the new version fails
+    /// to load, the previous version keeps running. Errors are added
+    /// to the report.
+    pub fn reload_changed(&mut self, lua_ctx: Context) -> rlua::Result<()> {
+        if self.last_poll.elapsed() < self.settings.reload_interval {
+            return Ok(());
+        }
+        self.last_poll = Instant::now();
+        self.reload_user_settings(lua_ctx)?;
+
+        for name in self.load_order.clone() {
+            let source = &self.mods[&name].source;
+            let modified = match source.refresh().and_then(|()| self.vfs.modified(&name)) {
+                Ok(modified) => modified,
+                Err(err) => {
+                    eprintln!("failed checking mod '{}' for changes: {}", name, err);
+                    continue;
+                }
+            };
+
+            if modified != self.mods[&name].modified {
+                println!("Reloading mod '{}'", name);
+                self.reload_mod(lua_ctx, &name)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Executes a mod's scripts again, replacing the running version.
+    pub fn reload_mod(&mut self, lua_ctx: Context, name: &str) -> rlua::Result<()> {
+        match self.execute_mod(lua_ctx, name) {
+            Ok(Some(previous)) => {
+                let environment = self.mods[name]
+                    .environment(lua_ctx)?
+                    .expect("reloaded mod has no environment");
+                if let Some(on_reload) = environment.raw_get::<_, Option<Function>>("on_reload")? {
+                    if let Err(err) = self.call_mod_function(lua_ctx, name, on_reload, previous) {
+                        self.report_hook_error(name, "on_reload", err);
+                    }
+                }
+            }
+            Ok(None) => {
+                // The mod failed to load before, so it has not been
+                // initialised yet.
+                self.call_on_load(lua_ctx, name)?;
+                self.update_enabled(lua_ctx)?;
+                if self.mods[name].state == ModState::Enabled {
+                    self.run_lifecycle_hook(lua_ctx, name, "on_init")?;
+                }
+            }
+            Err(err) => {
+                eprintln!(
+                    "failed reloading mod '{}', keeping previous version: {}",
+                    name, err
+                );
+                self.report.push(err);
+            }
+        }
+
+        // Environments and callbacks of replaced versions.
+        lua_ctx.expire_registry_values();
+
+        Ok(())
+    }
+
+    /// Executes the entry point of a mod in a new environment.
+    ///
+    /// On success the new environment replaces the one the mod had
+    /// before, which is returned. On failure the mod is left as it
+    /// was.
+    fn execute_mod<'lua>(
+        &mut self,
+        lua_ctx: Context<'lua>,
+        name: &str,
+    ) -> Result<Option<Table<'lua>>, ModLoadError> {
+        let entry_point = self.mods[name].entry_point.clone();
+        let lua_error = |err| ModLoadError::from_lua(name, &entry_point, err);
+
+        let shared = environment::read_only(lua_ctx, lua_ctx.globals()).map_err(lua_error)?;
+        let shared = capabilities::create_sandbox(
+            lua_ctx,
+            shared,
+            &self.mods[name].meta.capabilities,
+            self.vfs.clone(),
+            &self.budget,
+            name,
+        )
+        .map_err(lua_error)?;
+        let storage = self.storage(name);
+        let dependencies = self.mods[name].meta.dependencies.clone();
+        let fingerprint =
+            Fingerprint::of_source(&self.mods[name].source).map_err(|error| ModLoadError::Io {
+                path: self.mods[name].path().to_path_buf(),
+                error,
+            })?;
+        self.generations += 1;
+        let generation = self.generations;
+
+        let m = self
+            .mods
+            .get_mut(name)
+            .expect("mod in load order not registered");
+        m.modified = self.vfs.modified(name).unwrap_or_default();
+
+        let environment = environment::create_environment(lua_ctx, shared).map_err(lua_error)?;
+        let events = events::create_api(lua_ctx, self.events.clone(), name, generation)
+            .map_err(lua_error)?;
+        environment.set("events", events).map_err(lua_error)?;
+        let mods = exports::create_api(lua_ctx, name, dependencies, self.fingerprints.clone())
+            .map_err(lua_error)?;
+        environment.set("mods", mods).map_err(lua_error)?;
+        let require =
+            require::create_require(lua_ctx, self.resolver.clone(), name).map_err(lua_error)?;
+        environment.set("require", require).map_err(lua_error)?;
+        let storage = storage::create_api(lua_ctx, storage).map_err(lua_error)?;
+        environment.set("storage", storage).map_err(lua_error)?;
+        let settings = lua_ctx
+            .create_table_from(m.settings.clone())
+            .map_err(lua_error)?;
+        environment
+            .set(
+                "settings",
+                environment::read_only(lua_ctx, settings.clone()).map_err(lua_error)?,
+            )
+            .map_err(lua_error)?;
+
+        let previous = m.environment(lua_ctx).map_err(lua_error)?;
+        let previous_modules = require::take_modules(lua_ctx, name).map_err(lua_error)?;
+        require::register_environment(lua_ctx, name, Some(environment.clone()))
+            .map_err(lua_error)?;
+
+        let names = &self.names;
+        let policy = self.settings.conflict_policy(ConflictKind::GlobalFunction);
+        self.budget.enter(lua_ctx, name).map_err(lua_error)?;
+        let result = m.execute_entry_point(lua_ctx, environment.clone(), &self.vfs);
+        let result = match self.budget.leave() {
+            Some(exceeded) => Err(ModLoadError::Budget(exceeded)),
+            None => result,
+        };
+        let result = result.and_then(|()| {
+            let functions = global_functions(environment.clone()).map_err(lua_error)?;
+            let mut names = names.lock().unwrap();
+            names.unregister(ConflictKind::GlobalFunction, name);
+            for function in functions {
+                names.register(
+                    ConflictKind::GlobalFunction,
+                    &function,
+                    name,
+                    m.priority(),
+                    policy,
+                )?;
+            }
+            Ok(())
+        });
+
+        match result {
+            Ok(()) => {
+                self.events.lock().unwrap().unsubscribe(name, m.generation);
+                m.generation = generation;
+                m.environment = Some(
+                    lua_ctx
+                        .create_registry_value(environment)
+                        .map_err(lua_error)?,
+                );
+                m.settings_table =
+                    Some(lua_ctx.create_registry_value(settings).map_err(lua_error)?);
+                m.fingerprint = fingerprint;
+                self.update_fingerprints();
+
+                Ok(previous)
+            }
+            Err(err) => {
+                // Restore the names of the previous version, if any.
+                let mut names = self.names.lock().unwrap();
+                names.unregister(ConflictKind::GlobalFunction, name);
+                if let Some(previous) = &previous {
+                    for function in global_functions(previous.clone()).map_err(lua_error)? {
+                        let _ = names.register(
+                            ConflictKind::GlobalFunction,
+                            &function,
+                            name,
+                            m.priority(),
+                            policy,
+                        );
+                    }
+                }
+                self.events.lock().unwrap().unsubscribe(name, generation);
+                require::restore_modules(lua_ctx, name, previous_modules).map_err(lua_error)?;
+                require::register_environment(lua_ctx, name, previous).map_err(lua_error)?;
+
+                Err(err)
+            }
+        }
+    }
+
+    /// Calls the function with the given name in the environment of
+    /// every enabled mod, in load order.
+    ///
+    /// Mods that don't define the function are skipped. A mod whose
+    /// function raises an error is suspended, and the error is added
+    /// to the report.
+    pub fn call_hook<'lua, A>(
+        &mut self,
+        lua_ctx: Context<'lua>,
+        hook: &str,
+        args: A,
+    ) -> rlua::Result<()>
+    where
+        A: ToLuaMulti<'lua> + Clone,
+    {
+        for name in self.load_order.clone() {
+            let m = &self.mods[&name];
+            if m.state != ModState::Enabled {
+                continue;
+            }
+
+            if let Some(environment) = m.environment(lua_ctx)? {
+                if let Some(func) = environment.raw_get::<_, Option<Function>>(hook)? {
+                    if let Err(err) = self.call_mod_function(lua_ctx, &name, func, args.clone()) {
+                        self.suspend_failed(&name, err);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Delivers the events emitted by mods since the last dispatch.
+    ///
+    /// A mod whose callback raises an error is suspended, and the
+    /// error is added to the report.
+    pub fn dispatch_events(&mut self, lua_ctx: Context) -> rlua::Result<()> {
+        for failure in events::dispatch(&self.events, lua_ctx, &self.budget)? {
+            match failure {
+                CallbackFailure::Budget(exceeded) => self.suspend(exceeded),
+                CallbackFailure::Error { mod_name, error } => self.suspend_failed(&mod_name, error),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Limits the instructions and memory used by mods in the given
+    /// Lua state, according to the budgets in the settings.
+    ///
+    /// Also caps the memory of the whole Lua state, so scripts that
+    /// don't belong to any mod can't exhaust memory either.
+    pub fn install_budget(&mut self, lua: &Lua) {
+        self.budget = BudgetMeter::new(
+            self.settings.instruction_budget,
+            self.settings.memory_budget,
+            self.settings.budget_check_interval,
+        );
+        self.budget.install(lua);
+        self.runner = self.runner.with_budget(self.budget.clone());
+        lua.set_memory_limit(self.settings.memory_limit);
+    }
+
+    /// Starts a new frame, giving every mod its full instruction
+    /// budget again.
+    ///
+    /// Mods that failed in a script system during the last frame are
+    /// suspended.
+    pub fn new_frame(&mut self) {
+        self.budget.new_frame();
+
+        for failure in self.runner.take_failures() {
+            match failure {
+                CallbackFailure::Budget(exceeded) => self.suspend(exceeded),
+                CallbackFailure::Error { mod_name, error } => self.suspend_failed(&mod_name, error),
+            }
+        }
+    }
+
+    /// Calls into the enabled mods on behalf of script systems.
+    ///
+    /// The runner checks the budget installed when it was taken, so
+    /// it should be taken after `install_budget`.
+    pub fn runner(&self) -> ModRunner {
+        self.runner.clone()
+    }
+
+    /// Shares which mods are enabled, and their priorities, with the
+    /// runner after the state of a mod or the load order changed.
+    fn update_runner(&self) {
+        self.runner.set_priorities(
+            self.load_order
+                .iter()
+                .map(|name| (name.clone(), self.mods[name].priority()))
+                .collect(),
+        );
+        self.runner.set_enabled(
+            self.mods
+                .values()
+                .filter(|m| m.state == ModState::Enabled)
+                .map(|m| m.name().to_owned())
+                .collect(),
+        );
+    }
+
+    /// Calls a function of a mod, keeping track of its budget.
+    ///
+    /// A mod that goes over its budget is suspended, and the error is
+    /// not returned.
+    fn call_mod_function<'lua, A>(
+        &mut self,
+        lua_ctx: Context<'lua>,
+        name: &str,
+        func: Function<'lua>,
+        args: A,
+    ) -> rlua::Result<()>
+    where
+        A: ToLuaMulti<'lua>,
+    {
+        self.budget.enter(lua_ctx, name)?;
+        let result = func.call::<_, ()>(args);
+        match self.budget.leave() {
+            Some(exceeded) => {
+                self.suspend(exceeded);
+                Ok(())
+            }
+            None => result,
+        }
+    }
+
+    /// Stops calling hooks of a mod and delivering events to it.
+    fn suspend(&mut self, exceeded: BudgetExceeded) {
+        eprintln!("{}, suspending it", exceeded);
+        self.set_suspended(&exceeded.mod_name.clone());
+        self.report.push(exceeded);
+    }
+
+    /// Suspends a mod whose function raised an error, so a broken
+    /// mod doesn't fail again every frame.
+    fn suspend_failed(&mut self, name: &str, err: rlua::Error) {
+        let err = ModLoadError::from_lua(name, &self.mods[name].entry_point, err);
+        eprintln!("{}, suspending it", err);
+        self.set_suspended(name);
+        self.report.push(err);
+    }
+
+    fn set_suspended(&mut self, name: &str) {
+        if let Some(m) = self.mods.get_mut(name) {
+            m.state = ModState::Suspended;
+            self.events.lock().unwrap().set_muted(name, true);
+        }
+        self.update_runner();
+    }
+
+    /// Resumes a mod that was suspended for going over its budget or
+    /// raising an error.
+    ///
+    /// The mod is enabled again if the mod list allows it. Returns
+    /// false if the mod was not suspended.
+    pub fn resume_mod(&mut self, lua_ctx: Context, name: &str) -> rlua::Result<bool> {
+        match self.mods.get_mut(name) {
+            Some(m) if m.state == ModState::Suspended => {
+                m.state = ModState::Loaded;
+                self.update_enabled(lua_ctx)?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+}
+
+/// When a file was last modified, if it exists.
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Names of the functions a mod defined in its global table, other
+/// than hooks.
+fn global_functions(environment: Table) -> rlua::Result<Vec<String>> {
+    let mut functions = Vec::new();
+    for pair in environment.pairs::<Value, Value>() {
+        if let (Value::String(name), Value::Function(_)) = pair? {
+            let name = name.to_str()?;
+            if !HOOKS.contains(&name) && !MOD_API.contains(&name) {
+                functions.push(name.to_owned());
+            }
+        }
+    }
+
+    Ok(functions)
+}
+
+pub struct ModSettings {
+    /// Filename for initial script.
+    pub entry_point: String,
+    /// Filename of the manifest describing a mod.
+    pub manifest: String,
+    /// Directory that contains all mods.
+    pub directory_path: PathBuf,
+    /// File recording which mods are enabled, next to the mods directory.
+    pub mod_list_path: PathBuf,
+    /// File with the user's values for the settings declared by mods.
+    pub user_settings_path: PathBuf,
+    /// Directory holding the data saved by each mod, in a subdirectory
+    /// named after the mod.
+    pub data_directory_path: PathBuf,
+    /// Time between checks for changed mod files.
+    pub reload_interval: Duration,
+    /// What to do when mods register the same name, for each kind of
+    /// name. Kinds that are not listed only warn.
+    pub conflict_policies: BTreeMap<ConflictKind, ConflictPolicy>,
+    /// Instructions each mod may run per frame.
+    pub instruction_budget: u64,
+    /// Bytes each mod may allocate in one call into its scripts.
+    pub memory_budget: usize,
+    /// Bytes the whole Lua state may use.
+    pub memory_limit: Option<usize>,
+    /// Number of instructions between checks of the budgets.
+    pub budget_check_interval: u32,
+}
+
+impl ModSettings {
+    pub fn conflict_policy(&self, kind: ConflictKind) -> ConflictPolicy {
+        self.conflict_policies
+            .get(&kind)
+            .cloned()
+            .unwrap_or(ConflictPolicy::Warn)
+    }
+}
+
+impl fmt::Display for ModSettings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "entry_point: {},\nmanifest: {},\ndirectory_path: {},\nmod_list_path: {},\ndata_directory_path: {},\nuser_settings_path: {},\nreload_interval: {:?},\nconflict_policies: {:?},\ninstruction_budget: {},\nmemory_budget: {},\nmemory_limit: {:?},\nbudget_check_interval: {}",
+            self.entry_point,
+            self.manifest,
+            self.directory_path.to_string_lossy(),
+            self.mod_list_path.to_string_lossy(),
+            self.data_directory_path.to_string_lossy(),
+            self.user_settings_path.to_string_lossy(),
+            self.reload_interval,
+            self.conflict_policies,
+            self.instruction_budget,
+            self.memory_budget,
+            self.memory_limit,
+            self.budget_check_interval
+        )
+    }
+}
+
+/// Stage of a mod's lifecycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModState {
+    /// The mod's scripts are not running.
+    Unloaded,
+    /// The mod's scripts have run, but the mod is disabled.
+    Loaded,
+    /// The mod receives hooks and events.
+    Enabled,
+    /// The mod went over its budget or raised an error, and no longer
+    /// receives hooks and events until it is resumed.
+    Suspended,
+}
+
+pub struct Mod {
+    meta: ModMeta,
+    /// Directory or archive holding the mod's files.
+    source: ModSource,
+    /// Path to the script executed when the mod is loaded, relative
+    /// to the mod's root.
+    entry_point: String,
+    /// Global table of the mod's scripts, once loaded.
+    environment: Option<RegistryKey>,
+    state: ModState,
+    /// Version of the mod's scripts that is running.
+    generation: u32,
+    /// Modification times of the mod's files when its scripts were
+    /// last executed.
+    modified: BTreeMap<String, SystemTime>,
+    /// Current value of each setting declared by the mod.
+    settings: BTreeMap<String, SettingValue>,
+    /// Table behind the read-only `settings` seen by the running
+    /// version of the mod's scripts.
+    settings_table: Option<RegistryKey>,
+    /// Hash of the mod's files when its scripts were last executed.
+    fingerprint: Fingerprint,
+}
+
+impl Mod {
+    pub fn name(&self) -> &str {
+        &self.meta.name
+    }
+
+    pub fn meta(&self) -> &ModMeta {
+        &self.meta
+    }
+
+    pub fn state(&self) -> ModState {
+        self.state
+    }
+
+    pub fn priority(&self) -> u16 {
+        self.meta.priority
+    }
+
+    pub fn path(&self) -> &Path {
+        self.source.path()
+    }
+
+    /// Current value of each setting declared by the mod.
+    pub fn settings(&self) -> &BTreeMap<String, SettingValue> {
+        &self.settings
+    }
+
+    pub fn fingerprint(&self) -> Fingerprint {
+        self.fingerprint
+    }
+
+    /// Registers the names declared in a mod's manifest, and the paths
+    /// of its assets.
+    fn register_names(
+        &self,
+        names: &mut NameRegistry,
+        settings: &ModSettings,
+    ) -> Result<(), ModLoadError> {
+        let provides = &self.meta.provides;
+        let declared = provides
+            .events
+            .iter()
+            .map(|name| (ConflictKind::Event, name.clone()))
+            .chain(
+                provides
+                    .prefabs
+                    .iter()
+                    .map(|name| (ConflictKind::Prefab, name.clone())),
+            )
+            .chain(
+                provides
+                    .components
+                    .iter()
+                    .map(|name| (ConflictKind::Component, name.clone())),
+            );
+
+        let files = self.source.files().map_err(|error| ModLoadError::Io {
+            path: self.path().to_owned(),
+            error,
+        })?;
+        let assets = files
+            .into_iter()
+            .filter(|file| Vfs::is_shared(file))
+            .map(|file| (ConflictKind::Asset, file));
+
+        for (kind, name) in declared.chain(assets) {
+            let policy = settings.conflict_policy(kind);
+            names.register(kind, &name, self.name(), self.priority(), policy)?;
+        }
+
+        Ok(())
+    }
+
+    /// Retrieves the mod's global table, if its scripts are loaded.
+    pub fn environment<'lua>(&self, lua_ctx: Context<'lua>) -> rlua::Result<Option<Table<'lua>>> {
+        self.environment
+            .as_ref()
+            .map(|key| lua_ctx.registry_value(key))
+            .transpose()
+    }
+
+    fn execute_entry_point<'lua>(
+        &self,
+        lua_ctx: Context<'lua>,
+        environment: Table<'lua>,
+        vfs: &Vfs,
+    ) -> Result<(), ModLoadError> {
+        println!("Initialize script '{}/{}'", self.name(), self.entry_point);
+        let script = vfs
+            .read_to_string_from(self.name(), &self.entry_point)
+            .map_err(|error| ModLoadError::Io {
+                path: self.path().join(&self.entry_point),
+                error,
+            })?;
+
+        lua_ctx
+            .load(&script)
+            .set_name(&format!("@{}/{}", self.name(), self.entry_point))
+            .and_then(|chunk| chunk.set_environment(environment))
+            .and_then(|chunk| chunk.exec())
+            .map_err(|err| ModLoadError::from_lua(self.name(), &self.entry_point, err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    /// Creates a directory with a single mod, named `probe`.
+    fn create_root(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = env::temp_dir().join(format!("scripting-{}-test-{}", test, process::id()));
+        let directory = root.join(DEFAULT_DIRECTORY_NAME).join("probe");
+        fs::create_dir_all(&directory).unwrap();
+        for (path, contents) in files {
+            fs::write(directory.join(path), contents).unwrap();
+        }
+        root
+    }
+
+    fn state(mod_hub: &ModHub) -> ModState {
+        mod_hub
+            .mods()
+            .find(|m| m.name() == "probe")
+            .unwrap()
+            .state()
+    }
+
+    #[test]
+    fn mods_are_enabled_disabled_and_resumed() {
+        let root = create_root(
+            "lifecycle",
+            &[(
+                DEFAULT_ENTRY_POINT,
+                r#"
+                function on_update()
+                    error("broken update")
+                end
+                "#,
+            )],
+        );
+        let lua = Lua::new();
+        let mut mod_hub = ModHub::with_directory(&root);
+        mod_hub.discover().unwrap();
+
+        lua.context(|lua_ctx| {
+            mod_hub.load_scripts(lua_ctx).unwrap();
+            assert_eq!(state(&mod_hub), ModState::Enabled);
+
+            assert!(mod_hub.disable_mod(lua_ctx, "probe").unwrap());
+            assert_eq!(state(&mod_hub), ModState::Loaded);
+            assert!(!mod_hub.resume_mod(lua_ctx, "probe").unwrap());
+
+            assert!(mod_hub.enable_mod(lua_ctx, "probe").unwrap());
+            assert_eq!(state(&mod_hub), ModState::Enabled);
+
+            mod_hub.call_hook(lua_ctx, "on_update", ()).unwrap();
+            assert_eq!(state(&mod_hub), ModState::Suspended);
+            assert!(mod_hub.report().errors_for("probe").next().is_some());
+
+            assert!(mod_hub.resume_mod(lua_ctx, "probe").unwrap());
+            assert_eq!(state(&mod_hub), ModState::Enabled);
+
+            assert!(!mod_hub.enable_mod(lua_ctx, "missing").unwrap());
+        });
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn setting_changes_call_the_hook_once() {
+        let root = create_root(
+            "settings",
+            &[
+                (
+                    DEFAULT_MANIFEST,
+                    r#"
+                    name = "probe"
+
+                    [settings.speed]
+                    type = "number"
+                    min = 0
+                    default = 1
+                    "#,
+                ),
+                (
+                    DEFAULT_ENTRY_POINT,
+                    r#"
+                    changes = {}
+                    function on_setting_changed(name, value, previous)
+                        changes[#changes + 1] = name .. " " .. previous .. " " .. value
+                    end
+                    "#,
+                ),
+            ],
+        );
+        let lua = Lua::new();
+        let mut mod_hub = ModHub::with_directory(&root);
+        mod_hub.discover().unwrap();
+
+        lua.context(|lua_ctx| {
+            mod_hub.load_scripts(lua_ctx).unwrap();
+
+            let speed = SettingValue::Number;
+            mod_hub
+                .set_setting(lua_ctx, "probe", "speed", speed(2.0))
+                .unwrap();
+            mod_hub
+                .set_setting(lua_ctx, "probe", "speed", speed(2.0))
+                .unwrap();
+            assert!(mod_hub
+                .set_setting(lua_ctx, "probe", "speed", speed(-1.0))
+                .is_err());
+            assert!(mod_hub
+                .set_setting(lua_ctx, "probe", "speed", SettingValue::Bool(true))
+                .is_err());
+
+            let m = mod_hub.mods().find(|m| m.name() == "probe").unwrap();
+            assert_eq!(m.settings()["speed"], speed(2.0));
+            let environment = m.environment(lua_ctx).unwrap().unwrap();
+            let changes: Vec<String> = environment.get("changes").unwrap();
+            assert_eq!(changes, vec!["speed 1.0 2.0"]);
+        });
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,102 @@
+//! Persistent list of enabled mods
+
+use serde::{Deserialize, Serialize};
+use std::{fs, io, path::Path};
+
+/// Records which mods are enabled, and the order they were added in.
+///
+/// ```toml
+/// [[mods]]
+/// name = "ecs_example"
+/// enabled = true
+/// ```
+///
+/// Mods that are not listed are enabled. The position of a mod in
+/// the list breaks ties in the load order between mods of equal
+/// priority.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ModList {
+    #[serde(default)]
+    mods: Vec<ModListEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModListEntry {
+    pub name: String,
+    pub enabled: bool,
+}
+
+impl ModList {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Reads the list from the given file.
+    ///
+    /// A missing file results in an empty list.
+    pub fn load<P>(path: P) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(ModList::new());
+        }
+
+        let contents = fs::read_to_string(path)?;
+        toml::from_str(&contents).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    pub fn save<P>(&self, path: P) -> io::Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let contents =
+            toml::to_string(self).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        fs::write(path, contents)
+    }
+
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.mods
+            .iter()
+            .find(|entry| entry.name == name)
+            .map(|entry| entry.enabled)
+            .unwrap_or(true)
+    }
+
+    /// Position of the mod in the list. Unlisted mods come last.
+    pub fn position(&self, name: &str) -> usize {
+        self.mods
+            .iter()
+            .position(|entry| entry.name == name)
+            .unwrap_or(self.mods.len())
+    }
+
+    /// Enables or disables a mod, adding it to the end of the list
+    /// if it is not listed yet.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) {
+        match self.mods.iter_mut().find(|entry| entry.name == name) {
+            Some(entry) => entry.enabled = enabled,
+            None => self.mods.push(ModListEntry {
+                name: name.to_owned(),
+                enabled,
+            }),
+        }
+    }
+
+    /// Adds mods that are not listed yet to the end of the list, as
+    /// enabled.
+    ///
+    /// Mods that are listed but were not given are kept, so they keep
+    /// their state if they are installed again.
+    pub fn extend<'a, I>(&mut self, names: I)
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        for name in names {
+            if !self.mods.iter().any(|entry| entry.name == name) {
+                self.set_enabled(name, true);
+            }
+        }
+    }
+}
@@ -0,0 +1,228 @@
+//! Mod-aware module loading
+
+use super::Vfs;
+use rlua::{Context, Function, Table, Value};
+use std::{collections::BTreeMap, sync::Arc};
+
+/// Registry table holding the environment of every loaded mod.
+const ENVIRONMENTS_KEY: &str = "modding_environments";
+/// Registry table caching the modules each mod has loaded.
+const LOADED_KEY: &str = "modding_loaded";
+
+/// Dependencies of the mods that modules can be loaded from.
+pub struct ModuleResolver {
+    /// Files of the mods.
+    vfs: Arc<Vfs>,
+    /// Declared dependencies of each mod.
+    mods: BTreeMap<String, Vec<String>>,
+}
+
+impl ModuleResolver {
+    pub fn new(vfs: Arc<Vfs>) -> Self {
+        ModuleResolver {
+            vfs,
+            mods: BTreeMap::new(),
+        }
+    }
+
+    pub fn add_mod<S>(&mut self, name: S, dependencies: Vec<String>)
+    where
+        S: Into<String>,
+    {
+        self.mods.insert(name.into(), dependencies);
+    }
+
+    /// Splits a module name into the mod it belongs to and its
+    /// candidate file paths, relative to the mod's root.
+    ///
+    /// `foo.bar` resolves within the requiring mod, while
+    /// `othermod:foo.bar` resolves within `othermod`, which must be
+    /// a declared dependency of the requiring mod.
+    fn resolve<'a>(
+        &self,
+        requirer: &'a str,
+        module: &'a str,
+    ) -> rlua::Result<(&'a str, Vec<String>)> {
+        let (mod_name, module_path) = match module.find(':') {
+            Some(index) => (&module[..index], &module[index + 1..]),
+            None => (requirer, module),
+        };
+
+        if !self.mods.contains_key(mod_name) {
+            return Err(rlua::Error::RuntimeError(format!(
+                "module '{}' not found: mod '{}' is not loaded",
+                module, mod_name
+            )));
+        }
+
+        if mod_name != requirer {
+            let declared = self
+                .mods
+                .get(requirer)
+                .map(|dependencies| dependencies.iter().any(|d| d == mod_name))
+                .unwrap_or(false);
+
+            if !declared {
+                return Err(rlua::Error::RuntimeError(format!(
+                    "mod '{}' can't require '{}': '{}' is not a declared dependency",
+                    requirer, module, mod_name
+                )));
+            }
+        }
+
+        let segments: Vec<&str> = module_path.split('.').collect();
+        if segments
+            .iter()
+            .any(|s| s.is_empty() || s.contains('/') || s.contains('\\'))
+        {
+            return Err(rlua::Error::RuntimeError(format!(
+                "invalid module name '{}'",
+                module
+            )));
+        }
+
+        let base = segments.join("/");
+        Ok((
+            mod_name,
+            vec![format!("{}.lua", base), format!("{}/init.lua", base)],
+        ))
+    }
+}
+
+/// Registers the environment of a mod, so modules of that mod are
+/// executed within it.
+///
+/// Passing `None` removes the environment of a mod that failed to load.
+pub fn register_environment<'lua>(
+    lua_ctx: Context<'lua>,
+    mod_name: &str,
+    environment: Option<Table<'lua>>,
+) -> rlua::Result<()> {
+    registry_table(lua_ctx, ENVIRONMENTS_KEY)?.set(mod_name, environment)
+}
+
+/// Environment registered for a mod, if it's loaded.
+pub fn registered_environment<'lua>(
+    lua_ctx: Context<'lua>,
+    mod_name: &str,
+) -> rlua::Result<Option<Table<'lua>>> {
+    registry_table(lua_ctx, ENVIRONMENTS_KEY)?.get(mod_name)
+}
+
+/// Clears the cached modules of a mod, so they are executed again
+/// when next required.
+///
+/// Returns the cleared modules, so they can be restored if reloading
+/// the mod fails.
+pub fn take_modules<'lua>(
+    lua_ctx: Context<'lua>,
+    mod_name: &str,
+) -> rlua::Result<Option<Table<'lua>>> {
+    let loaded = registry_table(lua_ctx, LOADED_KEY)?;
+    let modules = loaded.get(mod_name)?;
+    loaded.set(mod_name, Value::Nil)?;
+
+    Ok(modules)
+}
+
+pub fn restore_modules<'lua>(
+    lua_ctx: Context<'lua>,
+    mod_name: &str,
+    modules: Option<Table<'lua>>,
+) -> rlua::Result<()> {
+    registry_table(lua_ctx, LOADED_KEY)?.set(mod_name, modules)
+}
+
+fn registry_table<'lua>(lua_ctx: Context<'lua>, key: &str) -> rlua::Result<Table<'lua>> {
+    match lua_ctx.named_registry_value::<_, Option<Table>>(key)? {
+        Some(table) => Ok(table),
+        None => {
+            let table = lua_ctx.create_table()?;
+            lua_ctx.set_named_registry_value(key, table.clone())?;
+            Ok(table)
+        }
+    }
+}
+
+/// Creates the `require` function for the scripts of a mod.
+///
+/// Each module is executed once, in the environment of the mod it
+/// belongs to, and its result is cached for later calls.
+pub fn create_require<'lua>(
+    lua_ctx: Context<'lua>,
+    resolver: Arc<ModuleResolver>,
+    mod_name: &str,
+) -> rlua::Result<Function<'lua>> {
+    let requirer = mod_name.to_owned();
+
+    lua_ctx.create_function(move |lua_ctx, module: String| {
+        let (mod_name, candidates) = resolver.resolve(&requirer, &module)?;
+
+        let loaded = registry_table(lua_ctx, LOADED_KEY)?;
+        let mod_loaded = match loaded.get::<_, Option<Table>>(mod_name)? {
+            Some(table) => table,
+            None => {
+                let table = lua_ctx.create_table()?;
+                loaded.set(mod_name, table.clone())?;
+                table
+            }
+        };
+
+        for candidate in &candidates {
+            match mod_loaded.get::<_, Value>(candidate.as_str())? {
+                Value::Nil => {}
+                cached => return Ok(cached),
+            }
+        }
+
+        let found = candidates
+            .iter()
+            .find(|candidate| resolver.vfs.exists_in(mod_name, candidate));
+        let candidate = match found {
+            Some(found) => found,
+            None => {
+                return Err(rlua::Error::RuntimeError(format!(
+                    "module '{}' not found in mod '{}', tried '{}'",
+                    module,
+                    mod_name,
+                    candidates.join("', '")
+                )))
+            }
+        };
+
+        let environment = registry_table(lua_ctx, ENVIRONMENTS_KEY)?
+            .get::<_, Option<Table>>(mod_name)?
+            .ok_or_else(|| {
+                rlua::Error::RuntimeError(format!(
+                    "module '{}' not found: mod '{}' failed to load",
+                    module, mod_name
+                ))
+            })?;
+
+        // Modules in directories may be overridden by other mods, but
+        // still run as part of the mod requiring them.
+        let supplier = resolver
+            .vfs
+            .resolve_for(mod_name, candidate)
+            .unwrap_or(mod_name);
+        let script = resolver
+            .vfs
+            .read_to_string_for(mod_name, candidate)
+            .map_err(rlua::Error::external)?;
+        let result = lua_ctx
+            .load(&script)
+            .set_name(&format!("@{}/{}", supplier, candidate))?
+            .set_environment(environment)?
+            .eval::<Value>()?;
+
+        // Like Lua's own require, modules without a result are
+        // recorded as `true` so they are not executed again.
+        let result = match result {
+            Value::Nil => Value::Boolean(true),
+            value => value,
+        };
+        mod_loaded.set(candidate.as_str(), result.clone())?;
+
+        Ok(result)
+    })
+}
@@ -0,0 +1,234 @@
+//! Calls into mods from outside the hub
+
+use super::{require, BudgetMeter, CallbackFailure, ConflictKind, ConflictPolicy, NameRegistry};
+use rlua::{Context, Function, Table, ToLuaMulti};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    mem,
+    sync::{Arc, Mutex},
+};
+
+/// Calls functions of mods from code that can't borrow the hub, such
+/// as the script systems run by a dispatcher.
+///
+/// Only enabled mods are called, within their budget. A mod whose
+/// function fails is not called again, and the failure is handed to
+/// the hub at the start of the next frame, which suspends the mod.
+///
+/// Names registered by the host on behalf of running mods go into the
+/// hub's registry, following the same conflict policies as the names
+/// declared in manifests.
+#[derive(Clone)]
+pub struct ModRunner {
+    /// Mods that are enabled, kept up to date by the hub.
+    enabled: Arc<Mutex<BTreeSet<String>>>,
+    /// Priority of every mod in the load order, kept up to date by the
+    /// hub.
+    priorities: Arc<Mutex<BTreeMap<String, u16>>>,
+    failures: Arc<Mutex<Vec<CallbackFailure>>>,
+    names: Arc<Mutex<NameRegistry>>,
+    policies: Arc<BTreeMap<ConflictKind, ConflictPolicy>>,
+    budget: BudgetMeter,
+}
+
+impl ModRunner {
+    pub fn new(
+        budget: BudgetMeter,
+        names: Arc<Mutex<NameRegistry>>,
+        policies: BTreeMap<ConflictKind, ConflictPolicy>,
+    ) -> Self {
+        ModRunner {
+            enabled: Arc::new(Mutex::new(BTreeSet::new())),
+            priorities: Arc::new(Mutex::new(BTreeMap::new())),
+            failures: Arc::new(Mutex::new(Vec::new())),
+            names,
+            policies: Arc::new(policies),
+            budget,
+        }
+    }
+
+    /// A runner sharing the state of this one, which checks the given
+    /// budget instead.
+    pub fn with_budget(&self, budget: BudgetMeter) -> Self {
+        ModRunner {
+            enabled: self.enabled.clone(),
+            priorities: self.priorities.clone(),
+            failures: self.failures.clone(),
+            names: self.names.clone(),
+            policies: self.policies.clone(),
+            budget,
+        }
+    }
+
+    pub fn is_enabled(&self, mod_name: &str) -> bool {
+        self.enabled.lock().unwrap().contains(mod_name)
+    }
+
+    /// Mod whose scripts are running, so that host functions can tell
+    /// which mod called them.
+    pub fn running(&self) -> Option<String> {
+        self.budget.running()
+    }
+
+    pub fn set_enabled(&self, enabled: BTreeSet<String>) {
+        *self.enabled.lock().unwrap() = enabled;
+    }
+
+    pub fn set_priorities(&self, priorities: BTreeMap<String, u16>) {
+        *self.priorities.lock().unwrap() = priorities;
+    }
+
+    /// Registers a name on behalf of the running mod, following the
+    /// conflict policy for its kind.
+    ///
+    /// Returns whether the mod owns the name afterwards, which it
+    /// doesn't when a mod with a higher priority keeps it. Fails with
+    /// the conflict when the policy rejects the mod.
+    pub fn register_name(&self, kind: ConflictKind, name: &str) -> rlua::Result<bool> {
+        let mod_name = self.running().ok_or_else(|| {
+            rlua::Error::RuntimeError(format!(
+                "{} '{}' can only be registered by mods",
+                kind, name
+            ))
+        })?;
+        let priority = self
+            .priorities
+            .lock()
+            .unwrap()
+            .get(&mod_name)
+            .cloned()
+            .unwrap_or(0);
+        let policy = self
+            .policies
+            .get(&kind)
+            .cloned()
+            .unwrap_or(ConflictPolicy::Warn);
+
+        let mut names = self.names.lock().unwrap();
+        names
+            .register(kind, name, &mod_name, priority, policy)
+            .map_err(rlua::Error::external)?;
+        Ok(names.owner(kind, name) == Some(mod_name.as_str()))
+    }
+
+    /// Current environment of a mod, so that callers find the
+    /// functions of the latest version of a reloaded mod.
+    pub fn environment<'lua>(
+        &self,
+        lua_ctx: Context<'lua>,
+        mod_name: &str,
+    ) -> rlua::Result<Option<Table<'lua>>> {
+        require::registered_environment(lua_ctx, mod_name)
+    }
+
+    /// Calls a function of a mod, if the mod is enabled, keeping track
+    /// of its budget.
+    ///
+    /// Errors raised by the function are recorded rather than
+    /// returned.
+    pub fn call<'lua, A>(
+        &self,
+        lua_ctx: Context<'lua>,
+        mod_name: &str,
+        func: Function<'lua>,
+        args: A,
+    ) -> rlua::Result<()>
+    where
+        A: ToLuaMulti<'lua>,
+    {
+        if !self.is_enabled(mod_name) {
+            return Ok(());
+        }
+
+        self.budget.enter(lua_ctx, mod_name)?;
+        let result = func.call::<_, ()>(args);
+        let failure = match (self.budget.leave(), result) {
+            (Some(exceeded), _) => CallbackFailure::Budget(exceeded),
+            (None, Err(error)) => CallbackFailure::Error {
+                mod_name: mod_name.to_owned(),
+                error,
+            },
+            (None, Ok(())) => return Ok(()),
+        };
+
+        self.enabled.lock().unwrap().remove(mod_name);
+        self.failures.lock().unwrap().push(failure);
+
+        Ok(())
+    }
+
+    /// Failures recorded since the last call.
+    pub fn take_failures(&self) -> Vec<CallbackFailure> {
+        mem::take(&mut self.failures.lock().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modding::ModLoadError;
+    use rlua::Lua;
+
+    fn create_runner(policy: ConflictPolicy, names: Arc<Mutex<NameRegistry>>) -> ModRunner {
+        let runner = ModRunner::new(
+            BudgetMeter::new(u64::MAX, usize::MAX, 1000),
+            names,
+            [(ConflictKind::Component, policy)]
+                .iter()
+                .cloned()
+                .collect(),
+        );
+        runner.set_priorities(
+            [("low".to_owned(), 1), ("high".to_owned(), 2)]
+                .iter()
+                .cloned()
+                .collect(),
+        );
+        runner
+    }
+
+    fn declare(lua: &Lua, runner: &ModRunner, mod_name: &str) -> rlua::Result<bool> {
+        lua.context(|lua_ctx| {
+            let declare_runner = runner.clone();
+            let declare = lua_ctx.create_function(move |_, name: String| {
+                declare_runner.register_name(ConflictKind::Component, &name)
+            })?;
+
+            runner.budget.enter(lua_ctx, mod_name)?;
+            let result = declare.call("Health");
+            runner.budget.leave();
+            result
+        })
+    }
+
+    #[test]
+    fn script_names_follow_the_conflict_policy() {
+        let lua = Lua::new();
+
+        let names = Arc::new(Mutex::new(NameRegistry::new()));
+        let runner = create_runner(ConflictPolicy::PriorityWins, names.clone());
+        assert!(declare(&lua, &runner, "low").unwrap());
+        assert!(declare(&lua, &runner, "high").unwrap());
+        assert!(!declare(&lua, &runner, "low").unwrap());
+        assert_eq!(names.lock().unwrap().conflicts().len(), 2);
+
+        let names = Arc::new(Mutex::new(NameRegistry::new()));
+        let runner = create_runner(ConflictPolicy::Error, names.clone());
+        assert!(declare(&lua, &runner, "low").unwrap());
+        let error = declare(&lua, &runner, "high").unwrap_err();
+        match ModLoadError::from_lua("high", "main.lua", error) {
+            ModLoadError::Conflict(conflict) => {
+                assert_eq!(conflict.kind, ConflictKind::Component);
+                assert_eq!(conflict.mod_name, "high");
+            }
+            other => panic!("expected a conflict, got {}", other),
+        }
+        assert_eq!(
+            names
+                .lock()
+                .unwrap()
+                .owner(ConflictKind::Component, "Health"),
+            Some("low")
+        );
+    }
+}
@@ -0,0 +1,274 @@
+//! User-tunable options declared by mods
+
+use rlua::{Context, ToLua, Value};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, error::Error, fmt, fs, io, path::Path};
+
+/// Declaration of one option in a mod's manifest.
+///
+/// ```toml
+/// [settings.show_hud]
+/// type = "bool"
+/// default = true
+///
+/// [settings.speed]
+/// type = "number"
+/// min = 0.5
+/// max = 4
+/// default = 1
+///
+/// [settings.difficulty]
+/// type = "enum"
+/// values = ["easy", "normal", "hard"]
+/// default = "normal"
+///
+/// [settings.player_name]
+/// type = "string"
+/// default = "Player"
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum SettingSchema {
+    Bool {
+        default: bool,
+    },
+    Number {
+        #[serde(default)]
+        min: Option<f64>,
+        #[serde(default)]
+        max: Option<f64>,
+        default: f64,
+    },
+    Enum {
+        values: Vec<String>,
+        default: String,
+    },
+    String {
+        default: String,
+    },
+}
+
+impl SettingSchema {
+    pub fn default_value(&self) -> SettingValue {
+        match self {
+            SettingSchema::Bool { default } => SettingValue::Bool(*default),
+            SettingSchema::Number { default, .. } => SettingValue::Number(*default),
+            SettingSchema::Enum { default, .. } | SettingSchema::String { default } => {
+                SettingValue::String(default.clone())
+            }
+        }
+    }
+
+    /// Checks that a value is allowed for this setting, and describes
+    /// the problem if it isn't.
+    pub fn validate(&self, value: &SettingValue) -> Result<(), String> {
+        match (self, value) {
+            (SettingSchema::Bool { .. }, SettingValue::Bool(_)) => Ok(()),
+            (SettingSchema::Number { min, max, .. }, SettingValue::Number(n)) => {
+                if min.is_some_and(|min| *n < min) || max.is_some_and(|max| *n > max) {
+                    Err(format!(
+                        "{} is outside the range {} to {}",
+                        n,
+                        min.map_or("-inf".to_owned(), |min| min.to_string()),
+                        max.map_or("inf".to_owned(), |max| max.to_string())
+                    ))
+                } else {
+                    Ok(())
+                }
+            }
+            (SettingSchema::Enum { values, .. }, SettingValue::String(s)) => {
+                if values.contains(s) {
+                    Ok(())
+                } else {
+                    Err(format!("'{}' is not one of '{}'", s, values.join("', '")))
+                }
+            }
+            (SettingSchema::String { .. }, SettingValue::String(_)) => Ok(()),
+            (schema, value) => Err(format!(
+                "expected a {}, found a {}",
+                schema.type_name(),
+                value.type_name()
+            )),
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            SettingSchema::Bool { .. } => "boolean",
+            SettingSchema::Number { .. } => "number",
+            SettingSchema::Enum { .. } | SettingSchema::String { .. } => "string",
+        }
+    }
+}
+
+/// Value of a setting, as stored in the settings file and seen by
+/// the mod's scripts.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SettingValue {
+    Bool(bool),
+    Number(f64),
+    String(String),
+}
+
+impl SettingValue {
+    /// Reads a value typed by the user.
+    ///
+    /// `true` and `false` are booleans, and text that reads as a number
+    /// is a number. Anything else is a string, which can be quoted to
+    /// keep it from reading as a boolean or number.
+    pub fn parse(text: &str) -> Self {
+        let text = text.trim();
+        match text {
+            "true" => SettingValue::Bool(true),
+            "false" => SettingValue::Bool(false),
+            _ if text.len() >= 2 && text.starts_with('"') && text.ends_with('"') => {
+                SettingValue::String(text[1..text.len() - 1].to_owned())
+            }
+            _ => match text.parse() {
+                Ok(n) => SettingValue::Number(n),
+                Err(_) => SettingValue::String(text.to_owned()),
+            },
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            SettingValue::Bool(_) => "boolean",
+            SettingValue::Number(_) => "number",
+            SettingValue::String(_) => "string",
+        }
+    }
+}
+
+impl fmt::Display for SettingValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SettingValue::Bool(b) => write!(f, "{}", b),
+            SettingValue::Number(n) => write!(f, "{}", n),
+            SettingValue::String(s) => write!(f, "'{}'", s),
+        }
+    }
+}
+
+impl<'lua> ToLua<'lua> for SettingValue {
+    fn to_lua(self, lua_ctx: Context<'lua>) -> rlua::Result<Value<'lua>> {
+        match self {
+            SettingValue::Bool(b) => Ok(Value::Boolean(b)),
+            SettingValue::Number(n) => Ok(Value::Number(n)),
+            SettingValue::String(s) => s.to_lua(lua_ctx),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SettingError {
+    pub mod_name: String,
+    pub setting: String,
+    pub message: String,
+}
+
+impl fmt::Display for SettingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid value for setting '{}' of mod '{}': {}",
+            self.setting, self.mod_name, self.message
+        )
+    }
+}
+
+impl Error for SettingError {}
+
+/// Values chosen by the user for the settings of each mod.
+///
+/// ```toml
+/// [ecs_example]
+/// speed = 2.5
+/// difficulty = "hard"
+/// ```
+///
+/// Settings that are not listed keep the default from the mod's
+/// manifest.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct UserSettings {
+    mods: BTreeMap<String, BTreeMap<String, SettingValue>>,
+}
+
+impl UserSettings {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Reads the settings from the given file.
+    ///
+    /// A missing file results in no overrides.
+    pub fn load<P>(path: P) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(UserSettings::new());
+        }
+
+        let contents = fs::read_to_string(path)?;
+        toml::from_str(&contents).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    pub fn save<P>(&self, path: P) -> io::Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let contents =
+            toml::to_string(self).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        fs::write(path, contents)
+    }
+
+    pub fn set<S>(&mut self, mod_name: S, setting: S, value: SettingValue)
+    where
+        S: Into<String>,
+    {
+        self.mods
+            .entry(mod_name.into())
+            .or_default()
+            .insert(setting.into(), value);
+    }
+
+    /// Combines the defaults declared by a mod with the user's values.
+    ///
+    /// Invalid or unknown user values are returned as errors, and the
+    /// default is used instead.
+    pub fn resolve(
+        &self,
+        mod_name: &str,
+        schema: &BTreeMap<String, SettingSchema>,
+    ) -> (BTreeMap<String, SettingValue>, Vec<SettingError>) {
+        let mut values: BTreeMap<String, SettingValue> = schema
+            .iter()
+            .map(|(name, setting)| (name.clone(), setting.default_value()))
+            .collect();
+        let mut errors = Vec::new();
+
+        for (name, value) in self.mods.get(mod_name).into_iter().flatten() {
+            let result = match schema.get(name) {
+                Some(setting) => setting.validate(value),
+                None => Err("the mod has no such setting".to_owned()),
+            };
+
+            match result {
+                Ok(()) => {
+                    values.insert(name.clone(), value.clone());
+                }
+                Err(message) => errors.push(SettingError {
+                    mod_name: mod_name.to_owned(),
+                    setting: name.clone(),
+                    message,
+                }),
+            }
+        }
+
+        (values, errors)
+    }
+}
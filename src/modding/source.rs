@@ -0,0 +1,266 @@
+//! Storage of a mod's files
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::SystemTime,
+};
+use zip::ZipArchive;
+
+/// Extension of archives that are loaded as mods.
+pub const ARCHIVE_EXTENSION: &str = "zip";
+
+/// Where the files of a mod are stored.
+///
+/// Paths given to a source are relative, separated by `/`, and
+/// already normalized.
+#[derive(Debug, Clone)]
+pub enum ModSource {
+    /// Unpacked mod directory.
+    Directory(PathBuf),
+    /// Zip archive containing the mod.
+    Archive(Archive),
+}
+
+impl ModSource {
+    /// Opens the mod at the given path, which is either a directory
+    /// or an archive.
+    pub fn open<P>(path: P) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        if path.is_dir() {
+            Ok(ModSource::Directory(path.to_path_buf()))
+        } else {
+            Ok(ModSource::Archive(Archive::open(path)?))
+        }
+    }
+
+    /// Checks whether a path is a mod archive.
+    pub fn is_archive<P>(path: P) -> bool
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        path.is_file()
+            && path
+                .extension()
+                .map(|extension| extension == ARCHIVE_EXTENSION)
+                .unwrap_or(false)
+    }
+
+    /// Location of the mod on disk.
+    pub fn path(&self) -> &Path {
+        match self {
+            ModSource::Directory(path) => path,
+            ModSource::Archive(archive) => &archive.path,
+        }
+    }
+
+    pub fn is_file(&self, path: &str) -> bool {
+        match self {
+            ModSource::Directory(root) => root.join(path).is_file(),
+            ModSource::Archive(archive) => archive.index.read().unwrap().files.contains(path),
+        }
+    }
+
+    pub fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        match self {
+            ModSource::Directory(root) => fs::read(root.join(path)),
+            ModSource::Archive(archive) => archive.read(path),
+        }
+    }
+
+    /// Lists the paths of all files in the mod.
+    pub fn files(&self) -> io::Result<Vec<String>> {
+        match self {
+            ModSource::Directory(root) => {
+                let mut files = Vec::new();
+                list_files(root, "", &mut files)?;
+                Ok(files)
+            }
+            ModSource::Archive(archive) => Ok(archive
+                .index
+                .read()
+                .unwrap()
+                .files
+                .iter()
+                .cloned()
+                .collect()),
+        }
+    }
+
+    /// Lists the files of the mod along with the time each was last
+    /// modified.
+    ///
+    /// Files in an archive all share the modification time of the
+    /// archive itself, as of when it was last opened.
+    pub fn modified(&self) -> io::Result<BTreeMap<String, SystemTime>> {
+        match self {
+            ModSource::Directory(root) => self
+                .files()?
+                .into_iter()
+                .map(|file| {
+                    let modified = fs::metadata(root.join(&file))?.modified()?;
+                    Ok((file, modified))
+                })
+                .collect(),
+            ModSource::Archive(archive) => {
+                let index = archive.index.read().unwrap();
+                Ok(index
+                    .files
+                    .iter()
+                    .map(|file| (file.clone(), index.modified))
+                    .collect())
+            }
+        }
+    }
+
+    /// Opens an archive again if it changed since it was opened, so
+    /// that every clone of the source sees its new files.
+    ///
+    /// Directories are always up to date.
+    pub fn refresh(&self) -> io::Result<()> {
+        match self {
+            ModSource::Directory(_) => Ok(()),
+            ModSource::Archive(archive) => archive.refresh(),
+        }
+    }
+}
+
+/// Zip archive containing a mod.
+///
+/// The mod's files are either stored at the root of the archive, or
+/// inside a single top level directory, as happens when a mod's
+/// directory is zipped as a whole.
+#[derive(Debug, Clone)]
+pub struct Archive {
+    path: PathBuf,
+    /// Shared by the clones of the archive, so that they all see the
+    /// new files once it's opened again.
+    index: Arc<RwLock<ArchiveIndex>>,
+}
+
+#[derive(Debug)]
+struct ArchiveIndex {
+    /// Directory inside the archive that holds the mod's files.
+    prefix: String,
+    /// Paths of the mod's files, without the prefix.
+    files: BTreeSet<String>,
+    /// When the archive was last modified, as of opening it.
+    modified: SystemTime,
+}
+
+impl Archive {
+    pub fn open<P>(path: P) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+
+        Ok(Archive {
+            path: path.to_path_buf(),
+            index: Arc::new(RwLock::new(ArchiveIndex::read(path)?)),
+        })
+    }
+
+    fn refresh(&self) -> io::Result<()> {
+        let modified = fs::metadata(&self.path)?.modified()?;
+        if modified != self.index.read().unwrap().modified {
+            *self.index.write().unwrap() = ArchiveIndex::read(&self.path)?;
+        }
+
+        Ok(())
+    }
+
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        use std::io::Read;
+
+        let index = self.index.read().unwrap();
+        if !index.files.contains(path) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "'{}' not found in archive '{}'",
+                    path,
+                    self.path.to_string_lossy()
+                ),
+            ));
+        }
+
+        let mut archive = ZipArchive::new(fs::File::open(&self.path)?).map_err(into_io_error)?;
+        let mut file = archive
+            .by_name(&format!("{}{}", index.prefix, path))
+            .map_err(into_io_error)?;
+
+        let mut buf = Vec::with_capacity(file.size() as usize);
+        file.read_to_end(&mut buf)?;
+
+        Ok(buf)
+    }
+}
+
+impl ArchiveIndex {
+    fn read(path: &Path) -> io::Result<Self> {
+        let modified = fs::metadata(path)?.modified()?;
+        let archive = ZipArchive::new(fs::File::open(path)?).map_err(into_io_error)?;
+
+        let names: Vec<&str> = archive
+            .file_names()
+            .filter(|name| !name.ends_with('/'))
+            .collect();
+
+        // A single top level directory is treated as the mod's root.
+        let prefix = match names.first().and_then(|name| name.find('/')) {
+            Some(index)
+                if names
+                    .iter()
+                    .all(|name| name.starts_with(&names[0][..=index])) =>
+            {
+                names[0][..=index].to_owned()
+            }
+            _ => String::new(),
+        };
+
+        let files = names
+            .iter()
+            .map(|name| name[prefix.len()..].to_owned())
+            .collect();
+
+        Ok(ArchiveIndex {
+            prefix,
+            files,
+            modified,
+        })
+    }
+}
+
+fn into_io_error(err: zip::result::ZipError) -> io::Error {
+    match err {
+        zip::result::ZipError::Io(err) => err,
+        err => io::Error::new(io::ErrorKind::InvalidData, err),
+    }
+}
+
+fn list_files(directory: &Path, prefix: &str, files: &mut Vec<String>) -> io::Result<()> {
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let path = if prefix.is_empty() {
+            name
+        } else {
+            format!("{}/{}", prefix, name)
+        };
+
+        if entry.file_type()?.is_dir() {
+            list_files(&entry.path(), &path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,285 @@
+//! Persistent data saved by mods
+
+use super::ScriptValue;
+use rlua::{Context, Table};
+use serde_json::{json, Map, Number, Value};
+use std::{fs, io, path::PathBuf};
+
+/// Version of the save file format, stored in every file so older
+/// saves can still be read when the format changes.
+const FORMAT_VERSION: u64 = 1;
+const EXTENSION: &str = "json";
+
+/// Values saved by one mod, kept as one JSON file per key in the
+/// mod's data directory.
+///
+/// ```json
+/// {
+///   "format": 1,
+///   "data": { "high_score": 1200, "names": ["a", "b"] }
+/// }
+/// ```
+///
+/// Tables are saved as JSON arrays when they are sequences, and as
+/// objects otherwise, so they must only have string keys.
+#[derive(Debug, Clone)]
+pub struct Storage {
+    directory: PathBuf,
+}
+
+impl Storage {
+    pub fn new<P>(directory: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        Storage {
+            directory: directory.into(),
+        }
+    }
+
+    /// Writes a value under the given key, replacing what was saved
+    /// before. Saving `nil` removes the key.
+    pub fn save(&self, key: &str, value: &ScriptValue) -> io::Result<()> {
+        let path = self.path(key)?;
+
+        if let ScriptValue::Nil = value {
+            return match fs::remove_file(&path) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+                _ => Ok(()),
+            };
+        }
+
+        let contents = json!({
+            "format": FORMAT_VERSION,
+            "data": to_json(value)?,
+        });
+        let contents = serde_json::to_string_pretty(&contents).map_err(invalid_data)?;
+
+        // Write to a temporary file first, so a failed write can't
+        // leave a truncated save behind.
+        fs::create_dir_all(&self.directory)?;
+        let temporary = path.with_extension("tmp");
+        fs::write(&temporary, contents)?;
+        fs::rename(&temporary, &path)
+    }
+
+    /// Reads the value saved under the given key, if any.
+    pub fn load(&self, key: &str) -> io::Result<Option<ScriptValue>> {
+        let path = self.path(key)?;
+
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+
+        let mut contents: Value = serde_json::from_str(&contents).map_err(invalid_data)?;
+        match contents.get("format").and_then(Value::as_u64) {
+            Some(FORMAT_VERSION) => {}
+            Some(format) => {
+                return Err(invalid_data(format!(
+                    "unsupported save format {}, expected {}",
+                    format, FORMAT_VERSION
+                )))
+            }
+            None => return Err(invalid_data("save format version is missing")),
+        }
+
+        match contents.get_mut("data") {
+            Some(data) => Ok(Some(from_json(data.take()))),
+            None => Err(invalid_data("saved data is missing")),
+        }
+    }
+
+    fn path(&self, key: &str) -> io::Result<PathBuf> {
+        let valid = !key.is_empty()
+            && !key.starts_with('.')
+            && key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.');
+
+        if valid {
+            Ok(self.directory.join(format!("{}.{}", key, EXTENSION)))
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "keys may only contain letters, digits, '_', '-' and '.'",
+            ))
+        }
+    }
+}
+
+fn invalid_data<E>(err: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+fn to_json(value: &ScriptValue) -> io::Result<Value> {
+    match value {
+        ScriptValue::Nil => Ok(Value::Null),
+        ScriptValue::Boolean(b) => Ok(Value::Bool(*b)),
+        ScriptValue::Integer(i) => Ok(Value::from(*i)),
+        ScriptValue::Number(n) => Number::from_f64(*n)
+            .map(Value::Number)
+            .ok_or_else(|| invalid_data(format!("can't save number {}", n))),
+        ScriptValue::String(s) => Ok(Value::String(s.clone())),
+        ScriptValue::Table(pairs) => {
+            // A sequence has exactly the keys 1 to n.
+            let mut items = vec![None; pairs.len()];
+            for (key, value) in pairs {
+                match key {
+                    ScriptValue::Integer(i) if *i >= 1 && *i as usize <= pairs.len() => {
+                        items[*i as usize - 1] = Some(value);
+                    }
+                    _ => break,
+                }
+            }
+            if items.iter().all(Option::is_some) {
+                return items
+                    .into_iter()
+                    .map(|value| to_json(value.unwrap()))
+                    .collect::<io::Result<_>>()
+                    .map(Value::Array);
+            }
+
+            let mut map = Map::new();
+            for (key, value) in pairs {
+                match key {
+                    ScriptValue::String(key) => {
+                        map.insert(key.clone(), to_json(value)?);
+                    }
+                    _ => return Err(invalid_data(
+                        "can't save tables with keys other than strings, unless they are sequences",
+                    )),
+                }
+            }
+
+            Ok(Value::Object(map))
+        }
+    }
+}
+
+fn from_json(value: Value) -> ScriptValue {
+    match value {
+        Value::Null => ScriptValue::Nil,
+        Value::Bool(b) => ScriptValue::Boolean(b),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => ScriptValue::Integer(i),
+            None => ScriptValue::Number(n.as_f64().unwrap_or_default()),
+        },
+        Value::String(s) => ScriptValue::String(s),
+        Value::Array(items) => ScriptValue::Table(
+            items
+                .into_iter()
+                .enumerate()
+                .map(|(index, value)| (ScriptValue::Integer(index as i64 + 1), from_json(value)))
+                .collect(),
+        ),
+        Value::Object(map) => ScriptValue::Table(
+            map.into_iter()
+                .map(|(key, value)| (ScriptValue::String(key), from_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+/// Creates the `storage` table through which a mod saves and loads
+/// its data.
+///
+/// ```lua
+/// storage.save("progress", { level = 3, items = { "key", "map" } })
+/// local progress = storage.load("progress") or { level = 1 }
+/// ```
+pub fn create_api<'lua>(lua_ctx: Context<'lua>, storage: Storage) -> rlua::Result<Table<'lua>> {
+    let api = lua_ctx.create_table()?;
+
+    let save_storage = storage.clone();
+    let save = lua_ctx.create_function(move |_, (key, value): (String, ScriptValue)| {
+        save_storage
+            .save(&key, &value)
+            .map_err(|err| rlua::Error::RuntimeError(format!("failed saving '{}': {}", key, err)))
+    })?;
+    api.set("save", save)?;
+
+    let load = lua_ctx.create_function(move |_, key: String| {
+        storage
+            .load(&key)
+            .map(|value| value.unwrap_or(ScriptValue::Nil))
+            .map_err(|err| rlua::Error::RuntimeError(format!("failed loading '{}': {}", key, err)))
+    })?;
+    api.set("load", load)?;
+
+    Ok(api)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    fn string(s: &str) -> ScriptValue {
+        ScriptValue::String(s.to_owned())
+    }
+
+    /// Table with the keys in the order JSON objects are read back in.
+    fn progress() -> ScriptValue {
+        ScriptValue::Table(vec![
+            (
+                string("items"),
+                ScriptValue::Table(vec![
+                    (ScriptValue::Integer(1), string("key")),
+                    (ScriptValue::Integer(2), string("map")),
+                ]),
+            ),
+            (string("level"), ScriptValue::Integer(3)),
+            (string("speed"), ScriptValue::Number(1.5)),
+            (string("won"), ScriptValue::Boolean(false)),
+        ])
+    }
+
+    #[test]
+    fn json_round_trips_values() {
+        let json = to_json(&progress()).unwrap();
+
+        assert_eq!(
+            json,
+            json!({
+                "items": ["key", "map"],
+                "level": 3,
+                "speed": 1.5,
+                "won": false,
+            })
+        );
+        assert_eq!(from_json(json), progress());
+    }
+
+    #[test]
+    fn json_rejects_unsaveable_values() {
+        assert!(to_json(&ScriptValue::Number(f64::NAN)).is_err());
+
+        let mixed_keys = ScriptValue::Table(vec![
+            (ScriptValue::Integer(1), string("a")),
+            (ScriptValue::Boolean(true), string("b")),
+        ]);
+        assert!(to_json(&mixed_keys).is_err());
+    }
+
+    #[test]
+    fn saved_values_load_back() {
+        let directory =
+            std::env::temp_dir().join(format!("scripting-storage-test-{}", process::id()));
+        let storage = Storage::new(&directory);
+
+        storage.save("progress", &progress()).unwrap();
+        assert_eq!(storage.load("progress").unwrap(), Some(progress()));
+
+        storage.save("progress", &ScriptValue::Nil).unwrap();
+        assert_eq!(storage.load("progress").unwrap(), None);
+
+        assert!(storage.save("../escape", &progress()).is_err());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,99 @@
+//! Plain script values that can be moved between environments
+
+use rlua::{Context, FromLua, ToLua, Value};
+
+/// Deepest table nesting that is copied, which also stops
+/// self-referencing tables from recursing forever.
+const MAX_DEPTH: usize = 32;
+
+/// Owned copy of a Lua value made of plain data.
+///
+/// Functions, userdata and threads can't be copied and are rejected.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptValue {
+    Nil,
+    Boolean(bool),
+    Integer(i64),
+    Number(f64),
+    String(String),
+    /// Key-value pairs of a table, in traversal order.
+    Table(Vec<(ScriptValue, ScriptValue)>),
+}
+
+impl ScriptValue {
+    fn from_lua_value(value: Value, depth: usize) -> rlua::Result<Self> {
+        match value {
+            Value::Nil => Ok(ScriptValue::Nil),
+            Value::Boolean(b) => Ok(ScriptValue::Boolean(b)),
+            Value::Integer(i) => Ok(ScriptValue::Integer(i)),
+            Value::Number(n) => Ok(ScriptValue::Number(n)),
+            Value::String(s) => Ok(ScriptValue::String(s.to_str()?.to_owned())),
+            Value::Table(table) => {
+                if depth >= MAX_DEPTH {
+                    return Err(rlua::Error::FromLuaConversionError {
+                        from: "table",
+                        to: "ScriptValue",
+                        message: Some("table is nested too deep or references itself".to_owned()),
+                    });
+                }
+
+                let mut pairs = Vec::new();
+                for pair in table.pairs::<Value, Value>() {
+                    let (key, value) = pair?;
+                    pairs.push((
+                        ScriptValue::from_lua_value(key, depth + 1)?,
+                        ScriptValue::from_lua_value(value, depth + 1)?,
+                    ));
+                }
+
+                Ok(ScriptValue::Table(pairs))
+            }
+            other => Err(rlua::Error::FromLuaConversionError {
+                from: type_name(&other),
+                to: "ScriptValue",
+                message: Some("only plain data can be copied".to_owned()),
+            }),
+        }
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Nil => "nil",
+        Value::Boolean(_) => "boolean",
+        Value::LightUserData(_) => "lightuserdata",
+        Value::Integer(_) => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Table(_) => "table",
+        Value::Function(_) => "function",
+        Value::Thread(_) => "thread",
+        Value::UserData(_) => "userdata",
+        Value::Error(_) => "error",
+    }
+}
+
+impl<'lua> FromLua<'lua> for ScriptValue {
+    fn from_lua(value: Value<'lua>, _lua_ctx: Context<'lua>) -> rlua::Result<Self> {
+        ScriptValue::from_lua_value(value, 0)
+    }
+}
+
+impl<'lua> ToLua<'lua> for ScriptValue {
+    fn to_lua(self, lua_ctx: Context<'lua>) -> rlua::Result<Value<'lua>> {
+        match self {
+            ScriptValue::Nil => Ok(Value::Nil),
+            ScriptValue::Boolean(b) => Ok(Value::Boolean(b)),
+            ScriptValue::Integer(i) => Ok(Value::Integer(i)),
+            ScriptValue::Number(n) => Ok(Value::Number(n)),
+            ScriptValue::String(s) => s.to_lua(lua_ctx),
+            ScriptValue::Table(pairs) => {
+                let table = lua_ctx.create_table()?;
+                for (key, value) in pairs {
+                    table.raw_set(key, value)?;
+                }
+                Ok(Value::Table(table))
+            }
+        }
+    }
+}
@@ -0,0 +1,254 @@
+//! Layered virtual file system over mod directories and archives
+
+use super::ModSource;
+use std::{collections::BTreeMap, io, time::SystemTime};
+
+/// Virtual file system made of mods mounted on top of each other.
+///
+/// Mod directories and mod archives are interchangeable.
+///
+/// Paths are relative, separated by `/`, and may not leave the mod's
+/// directory or archive.
+///
+/// Files in the directories of mods, such as `textures/player.png` or
+/// `ai/brain.lua`, are shared: when more than one mod supplies such a
+/// path, the mod that was mounted last wins. The hub mounts mods so
+/// that this is the mod that wins the asset conflict: by default, mods
+/// with a higher priority override the files of lower priority mods,
+/// and mods override the files of their dependencies of the same
+/// priority. Files at the root of a mod, such as its manifest or entry
+/// point, belong to that mod alone.
+#[derive(Default)]
+pub struct Vfs {
+    mounts: Vec<Mount>,
+}
+
+struct Mount {
+    mod_name: String,
+    source: ModSource,
+}
+
+impl Vfs {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Mounts a mod over the ones mounted before it.
+    pub fn mount<S>(&mut self, mod_name: S, source: ModSource)
+    where
+        S: Into<String>,
+    {
+        self.mounts.push(Mount {
+            mod_name: mod_name.into(),
+            source,
+        });
+    }
+
+    /// Finds the mod that supplies the file at the given path.
+    pub fn resolve(&self, path: &str) -> Option<&str> {
+        let path = normalize(path)?;
+
+        self.mounts
+            .iter()
+            .rev()
+            .find(|mount| mount.source.is_file(&path))
+            .map(|mount| mount.mod_name.as_str())
+    }
+
+    /// Reads the file at the given path, as supplied by the mod with
+    /// the highest precedence.
+    pub fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        match self.resolve(path) {
+            Some(mod_name) => self.read_from(mod_name, path),
+            None => Err(not_found(path)),
+        }
+    }
+
+    /// Checks whether files at the given path are shared between mods,
+    /// rather than belonging to a single mod.
+    pub fn is_shared(path: &str) -> bool {
+        normalize(path).is_some_and(|path| path.contains('/'))
+    }
+
+    /// Finds the mod that supplies the file a mod sees at the given
+    /// path: the mod with the highest precedence for shared paths, and
+    /// the mod itself otherwise.
+    pub fn resolve_for(&self, mod_name: &str, path: &str) -> Option<&str> {
+        if Vfs::is_shared(path) {
+            self.resolve(path)
+        } else if self.exists_in(mod_name, path) {
+            self.mount_of(mod_name).map(|mount| mount.mod_name.as_str())
+        } else {
+            None
+        }
+    }
+
+    /// Reads the file a mod sees at the given path, which comes from
+    /// the mod with the highest precedence if the path is shared.
+    pub fn read_for(&self, mod_name: &str, path: &str) -> io::Result<Vec<u8>> {
+        if Vfs::is_shared(path) {
+            self.read(path)
+        } else {
+            self.read_from(mod_name, path)
+        }
+    }
+
+    pub fn read_to_string_for(&self, mod_name: &str, path: &str) -> io::Result<String> {
+        into_string(self.read_for(mod_name, path)?)
+    }
+
+    /// Reads the file at the given path from one specific mod,
+    /// ignoring files of other mods.
+    pub fn read_from(&self, mod_name: &str, path: &str) -> io::Result<Vec<u8>> {
+        match (self.mount_of(mod_name), normalize(path)) {
+            (Some(mount), Some(path)) => mount.source.read(&path),
+            _ => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("mod '{}' has no file '{}'", mod_name, path),
+            )),
+        }
+    }
+
+    pub fn read_to_string_from(&self, mod_name: &str, path: &str) -> io::Result<String> {
+        into_string(self.read_from(mod_name, path)?)
+    }
+
+    /// Checks whether one specific mod contains a file at the given path.
+    pub fn exists_in(&self, mod_name: &str, path: &str) -> bool {
+        match (self.mount_of(mod_name), normalize(path)) {
+            (Some(mount), Some(path)) => mount.source.is_file(&path),
+            _ => false,
+        }
+    }
+
+    /// Lists every shared path in the file system, along with the mod
+    /// that supplies it.
+    pub fn sources(&self) -> io::Result<BTreeMap<String, String>> {
+        let mut sources = BTreeMap::new();
+
+        for mount in &self.mounts {
+            for file in mount.source.files()? {
+                if Vfs::is_shared(&file) {
+                    sources.insert(file, mount.mod_name.clone());
+                }
+            }
+        }
+
+        Ok(sources)
+    }
+
+    /// Lists the files of one specific mod, along with the time each
+    /// was last modified.
+    pub fn modified(&self, mod_name: &str) -> io::Result<BTreeMap<String, SystemTime>> {
+        match self.mount_of(mod_name) {
+            Some(mount) => mount.source.modified(),
+            None => Ok(BTreeMap::new()),
+        }
+    }
+
+    fn mount_of(&self, mod_name: &str) -> Option<&Mount> {
+        self.mounts.iter().find(|mount| mount.mod_name == mod_name)
+    }
+}
+
+/// Converts a virtual path to the normalized form used by mod
+/// sources, rejecting paths that are absolute or step outside of
+/// the mod.
+fn normalize(path: &str) -> Option<String> {
+    let mut segments = Vec::new();
+
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => return None,
+            segment if segment.contains('\\') || segment.contains(':') => return None,
+            segment => segments.push(segment),
+        }
+    }
+
+    if segments.is_empty() {
+        None
+    } else {
+        Some(segments.join("/"))
+    }
+}
+
+fn not_found(path: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("no mod supplies '{}'", path),
+    )
+}
+
+fn into_string(bytes: Vec<u8>) -> io::Result<String> {
+    String::from_utf8(bytes).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, path::Path, process};
+
+    fn create_mod(root: &Path, name: &str, files: &[(&str, &str)]) -> ModSource {
+        let directory = root.join(name);
+        for (path, contents) in files {
+            let path = directory.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        ModSource::open(directory).unwrap()
+    }
+
+    #[test]
+    fn shared_paths_come_from_the_last_mounted_mod() {
+        let root = std::env::temp_dir().join(format!("scripting-vfs-test-{}", process::id()));
+        let base = create_mod(
+            &root,
+            "base",
+            &[
+                ("main.lua", "-- base"),
+                ("textures/player.png", "base player"),
+                ("ai/brain.lua", "-- base brain"),
+            ],
+        );
+        let patch = create_mod(
+            &root,
+            "patch",
+            &[
+                ("main.lua", "-- patch"),
+                ("textures/player.png", "patch player"),
+            ],
+        );
+        let mut vfs = Vfs::new();
+        vfs.mount("base", base);
+        vfs.mount("patch", patch);
+
+        assert_eq!(vfs.read("textures/player.png").unwrap(), b"patch player");
+        assert_eq!(
+            vfs.read_to_string_for("base", "textures/player.png")
+                .unwrap(),
+            "patch player"
+        );
+        assert_eq!(vfs.resolve_for("patch", "ai/brain.lua"), Some("base"));
+
+        // Files at the root of a mod are its own.
+        assert_eq!(
+            vfs.read_to_string_for("base", "main.lua").unwrap(),
+            "-- base"
+        );
+        assert_eq!(vfs.resolve_for("patch", "main.lua"), Some("patch"));
+        assert!(vfs.read_for("patch", "missing.lua").is_err());
+        assert!(vfs.read_for("base", "../patch/main.lua").is_err());
+
+        let sources = vfs.sources().unwrap();
+        assert_eq!(
+            sources.into_iter().collect::<Vec<_>>(),
+            [
+                ("ai/brain.lua".to_owned(), "base".to_owned()),
+                ("textures/player.png".to_owned(), "patch".to_owned()),
+            ]
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
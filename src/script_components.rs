@@ -0,0 +1,145 @@
+//! Components declared by scripts
+
+use crate::modding::ScriptValue;
+use specs::prelude::*;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, RwLock},
+};
+
+/// Fields of one script component, by name.
+pub type ScriptFields = BTreeMap<String, ScriptValue>;
+
+/// Script component types, by name, along with the default value of
+/// each of their fields.
+///
+/// Shared between the `World` and the `component` function of the
+/// scripts, so component types can be declared while mods load.
+#[derive(Clone, Default)]
+pub struct ComponentRegistry {
+    schemas: Arc<RwLock<HashMap<String, ScriptFields>>>,
+}
+
+impl ComponentRegistry {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Declares a component type, replacing any earlier declaration.
+    ///
+    /// Which mod may declare a name is settled by the name registry of
+    /// the mods. Components attached before keep their fields.
+    pub fn define<S>(&self, name: S, fields: ScriptFields)
+    where
+        S: Into<String>,
+    {
+        self.schemas.write().unwrap().insert(name.into(), fields);
+    }
+
+    /// Default values of the fields of a component type.
+    pub fn schema(&self, name: &str) -> Option<ScriptFields> {
+        let schemas = self.schemas.read().unwrap();
+        schemas.get(name).cloned()
+    }
+
+    /// Checks the given fields against the declaration of a component
+    /// type, and fills in the missing ones with their defaults.
+    pub fn instantiate(&self, name: &str, fields: ScriptFields) -> Result<ScriptFields, String> {
+        let mut component = self
+            .schema(name)
+            .ok_or_else(|| format!("unknown component '{}'", name))?;
+        self.update(name, &mut component, fields)?;
+
+        Ok(component)
+    }
+
+    /// Overwrites some fields of a component, checking them against
+    /// the declaration of its type.
+    ///
+    /// A field keeps the type of its default value, except that
+    /// integers and floats are interchangeable.
+    pub fn update(
+        &self,
+        name: &str,
+        component: &mut ScriptFields,
+        fields: ScriptFields,
+    ) -> Result<(), String> {
+        let schemas = self.schemas.read().unwrap();
+        let schema = schemas
+            .get(name)
+            .ok_or_else(|| format!("unknown component '{}'", name))?;
+
+        for (field, value) in &fields {
+            match schema.get(field) {
+                Some(default) if type_name(default) == type_name(value) => {}
+                Some(default) => {
+                    return Err(format!(
+                        "field '{}' of component '{}' is a {}, not a {}",
+                        field,
+                        name,
+                        type_name(default),
+                        type_name(value)
+                    ))
+                }
+                None => return Err(format!("component '{}' has no field '{}'", name, field)),
+            }
+        }
+
+        component.extend(fields);
+        Ok(())
+    }
+}
+
+fn type_name(value: &ScriptValue) -> &'static str {
+    match value {
+        ScriptValue::Nil => "nil",
+        ScriptValue::Boolean(_) => "boolean",
+        ScriptValue::Integer(_) | ScriptValue::Number(_) => "number",
+        ScriptValue::String(_) => "string",
+        ScriptValue::Table(_) => "table",
+    }
+}
+
+/// Script components attached to an entity, by name.
+///
+/// Rust systems can read them with a `ReadStorage<ScriptComponents>`:
+///
+/// ```ignore
+/// let hp = script_components
+///     .get(entity)
+///     .and_then(|components| components.get("Health"))
+///     .and_then(|health| health.get("hp"));
+/// ```
+#[derive(Component, Debug, Default, Clone)]
+pub struct ScriptComponents {
+    components: HashMap<String, ScriptFields>,
+}
+
+impl ScriptComponents {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ScriptFields> {
+        self.components.get(name)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut ScriptFields> {
+        self.components.get_mut(name)
+    }
+
+    pub fn insert<S>(&mut self, name: S, fields: ScriptFields) -> Option<ScriptFields>
+    where
+        S: Into<String>,
+    {
+        self.components.insert(name.into(), fields)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<ScriptFields> {
+        self.components.remove(name)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.components.is_empty()
+    }
+}
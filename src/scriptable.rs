@@ -1,7 +1,10 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
+use crate::modding::{ModHub, ModLoadError, ModRunner, ModState};
 use crossbeam::channel::{Receiver, Sender};
-use rlua::{Function, Lua, RegistryKey, UserData, UserDataMethods};
+use rlua::{
+    Context, Function, Lua, MetaMethod, RegistryKey, Table, UserData, UserDataMethods, Value,
+};
 use shred::{
     cell::{Ref, RefMut},
     Accessor, AccessorCow, CastFrom, DynamicSystemData, MetaTable,
@@ -24,15 +27,62 @@ impl ResourceTable {
         self.map.insert(name.to_owned(), ResourceId::new::<T>());
     }
 
-    pub fn get(&self, name: &str) -> ResourceId {
-        self.map.get(name).cloned().unwrap()
+    pub fn get(&self, name: &str) -> Option<ResourceId> {
+        self.map.get(name).cloned()
+    }
+
+    /// Looks up the ids of the named resources, failing with the first
+    /// name that was not registered.
+    ///
+    /// A resource named more than once is only listed the first time,
+    /// as it can only be borrowed once.
+    pub fn resolve<S>(&self, names: &[S]) -> Result<Vec<(String, ResourceId)>, String>
+    where
+        S: AsRef<str>,
+    {
+        let mut resolved: Vec<(String, ResourceId)> = Vec::new();
+        for name in names {
+            let name = name.as_ref();
+            let id = self.get(name).ok_or_else(|| name.to_owned())?;
+            if !resolved.iter().any(|(_, resolved)| *resolved == id) {
+                resolved.push((name.to_owned(), id));
+            }
+        }
+
+        Ok(resolved)
     }
 }
 
+/// Name and type of a field exposed through `Reflection`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldInfo {
+    pub name: &'static str,
+    /// Rust type of the field, as written in the struct.
+    pub type_name: &'static str,
+}
+
 /// Trait for dynamic script resources.
 ///
-/// Used for upcasting values out of the `ReflectionTable`.
-pub trait Reflection {}
+/// Used for upcasting values out of the `ReflectionTable`, and for
+/// accessing their fields from scripts by name.
+///
+/// Usually implemented with `#[derive(Reflection)]`.
+pub trait Reflection {
+    /// Fields that can be read and written by name.
+    fn fields(&self) -> &'static [FieldInfo];
+
+    /// Value of the named field, or `nil` if there is no such field.
+    fn get_field<'lua>(&self, lua_ctx: Context<'lua>, name: &str) -> rlua::Result<Value<'lua>>;
+
+    /// Sets the named field, failing if there is no such field or the
+    /// value has the wrong type.
+    fn set_field<'lua>(
+        &mut self,
+        lua_ctx: Context<'lua>,
+        name: &str,
+        value: Value<'lua>,
+    ) -> rlua::Result<()>;
+}
 
 unsafe impl<T> CastFrom<T> for dyn Reflection
 where
@@ -115,10 +165,18 @@ impl<'a> DynamicSystemData<'a> for ScriptSystemData<'a> {
 }
 
 pub struct ScriptSystem {
+    /// Name of the system, prefixed with the name of its mod.
+    name: String,
+    /// Mod that declared the system.
+    mod_name: String,
     /// Lists of resources required for the system to run.
     dependencies: Dependencies,
-    /// Identifier of Lua function to be executed on system run.
-    callback_key: RegistryKey,
+    /// Names that scripts use for the resources in `dependencies`, in
+    /// the same order.
+    read_names: Vec<String>,
+    write_names: Vec<String>,
+    /// Calls the system's `run` function while its mod is enabled.
+    runner: ModRunner,
     /// Channel for sending Lua state back.
     sender: Sender<Lua>,
     /// Channel for receiving Lua state on system run.
@@ -127,22 +185,49 @@ pub struct ScriptSystem {
 
 impl<'a> ScriptSystem {
     pub fn new(
+        name: String,
+        mod_name: String,
+        runner: ModRunner,
         sender: Sender<Lua>,
         receiver: Receiver<Lua>,
-        callback_key: RegistryKey,
-        reads: &[ResourceId],
-        writes: &[ResourceId],
+        reads: &[(String, ResourceId)],
+        writes: &[(String, ResourceId)],
     ) -> Self {
         ScriptSystem {
+            name,
+            mod_name,
             dependencies: Dependencies {
-                reads: reads.iter().cloned().collect(),
-                writes: writes.iter().cloned().collect(),
+                reads: reads.iter().map(|(_, id)| id.clone()).collect(),
+                writes: writes.iter().map(|(_, id)| id.clone()).collect(),
             },
-            callback_key,
+            read_names: reads.iter().map(|(name, _)| name.clone()).collect(),
+            write_names: writes.iter().map(|(name, _)| name.clone()).collect(),
+            runner,
             sender,
             receiver,
         }
     }
+
+    /// The `run` function of the system in the current version of its
+    /// mod, which may have been reloaded since the system was added.
+    ///
+    /// Returns `None` if the mod no longer declares the system.
+    fn run_function<'lua>(&self, lua_ctx: Context<'lua>) -> rlua::Result<Option<Function<'lua>>> {
+        let environment = match self.runner.environment(lua_ctx, &self.mod_name)? {
+            Some(environment) => environment,
+            None => return Ok(None),
+        };
+        let systems = match environment.raw_get::<_, Option<Table>>("systems")? {
+            Some(systems) => systems,
+            None => return Ok(None),
+        };
+
+        let name = &self.name[self.mod_name.len() + 1..];
+        match systems.get::<_, Option<Table>>(name)? {
+            Some(system) => system.get("run"),
+            None => Ok(None),
+        }
+    }
 }
 
 impl<'a> System<'a> for ScriptSystem {
@@ -156,49 +241,63 @@ impl<'a> System<'a> for ScriptSystem {
 
         let meta_table = data.meta_table;
 
-        let script_data = ScriptResourceData {
-            reads: data
-                .reads
-                .iter()
-                .map(|resource| {
-                    let res = Box::as_ref(resource);
+        let reads = data.reads.iter().map(|resource| {
+            let res = Box::as_ref(resource);
 
-                    let res: &dyn Reflection = meta_table
-                        .get(res)
-                        .expect("resource not registered in meta table");
+            let res: &dyn Reflection = meta_table
+                .get(res)
+                .expect("resource not registered in meta table");
 
-                    res
-                })
-                .collect(),
-            writes: data
-                .writes
-                .iter_mut()
-                .map(|resource| {
-                    let res = Box::as_mut(resource);
-
-                    let res: &mut dyn Reflection = meta_table
-                        .get_mut(res)
-                        .expect("resource not registered in meta table");
-
-                    res
-                })
-                .collect(),
-        };
+            ResourceAccess::Read(res)
+        });
+        let writes = data.writes.iter_mut().map(|resource| {
+            let res = Box::as_mut(resource);
+
+            let res: &mut dyn Reflection = meta_table
+                .get_mut(res)
+                .expect("resource not registered in meta table");
+
+            ResourceAccess::Write(res)
+        });
+        let names = self.read_names.iter().chain(self.write_names.iter());
 
         let result: rlua::Result<()> = lua.context(|lua_ctx| {
-            lua_ctx.scope(|scope| {
-                let sys_func = lua_ctx.registry_value::<Function>(&self.callback_key)?;
-                let args = scope.create_nonstatic_userdata(script_data)?;
-                sys_func.call(args)?;
+            if !self.runner.is_enabled(&self.mod_name) {
+                return Ok(());
+            }
+            let run = match self.run_function(lua_ctx)? {
+                Some(run) => run,
+                None => return Ok(()),
+            };
 
-                Ok(())
+            lua_ctx.scope(|scope| {
+                // Resources can only be reached through these proxies
+                // while the system runs.
+                let mut resources = HashMap::new();
+                for (name, access) in names.zip(reads.chain(writes)) {
+                    let writable = access.is_writable();
+                    let proxy = scope.create_nonstatic_userdata(ResourceProxy {
+                        name: name.clone(),
+                        access,
+                    })?;
+                    resources.insert(
+                        name.clone(),
+                        (lua_ctx.create_registry_value(proxy)?, writable),
+                    );
+                }
+
+                let args = scope.create_nonstatic_userdata(ScriptResourceData { resources })?;
+                self.runner.call(lua_ctx, &self.mod_name, run, args)
             })?;
 
+            // Proxies of this run
+            lua_ctx.expire_registry_values();
+
             Ok(())
         });
 
         if let Err(err) = result {
-            eprintln!("script system error {}", err);
+            eprintln!("script system '{}' error {}", self.name, err);
         }
 
         self.sender
@@ -211,19 +310,519 @@ impl<'a> System<'a> for ScriptSystem {
     }
 
     fn setup(&mut self, world: &mut World) {
-        world.insert(ReflectionTable::new());
+        // Keep the resources registered by other systems, or before
+        // the dispatcher was set up.
+        world
+            .entry::<ReflectionTable>()
+            .or_insert_with(ReflectionTable::new);
     }
 }
 
-pub struct ScriptResourceData<'a> {
-    reads: Vec<&'a dyn Reflection>,
-    writes: Vec<&'a mut dyn Reflection>,
+/// Resources declared by a script system, passed to its `run`
+/// function.
+///
+/// ```lua
+/// run = function(data)
+///     local dt = data:read("DeltaTime").seconds
+///     data:write("Score").value = 3
+/// end
+/// ```
+pub struct ScriptResourceData {
+    /// Proxy of each resource, and whether it was declared in `writes`.
+    resources: HashMap<String, (RegistryKey, bool)>,
+}
+
+impl ScriptResourceData {
+    fn proxy<'lua>(
+        &self,
+        lua_ctx: Context<'lua>,
+        name: &str,
+        write: bool,
+    ) -> rlua::Result<Value<'lua>> {
+        match self.resources.get(name) {
+            Some((_, false)) if write => Err(rlua::Error::RuntimeError(format!(
+                "resource '{}' is not declared in the system's writes",
+                name
+            ))),
+            Some((key, _)) => lua_ctx.registry_value(key),
+            None => Err(rlua::Error::RuntimeError(format!(
+                "resource '{}' is not declared by the system",
+                name
+            ))),
+        }
+    }
 }
 
-impl<'a> UserData for ScriptResourceData<'a> {
+impl UserData for ScriptResourceData {
     fn add_methods<'lua, T: UserDataMethods<'lua, Self>>(methods: &mut T) {
-        methods.add_method("read", |lua_ctx, data, ()| Ok(()));
+        methods.add_method("read", |lua_ctx, data, name: String| {
+            data.proxy(lua_ctx, &name, false)
+        });
+
+        methods.add_method("write", |lua_ctx, data, name: String| {
+            data.proxy(lua_ctx, &name, true)
+        });
+    }
+}
+
+enum ResourceAccess<'a> {
+    Read(&'a dyn Reflection),
+    Write(&'a mut dyn Reflection),
+}
+
+impl<'a> ResourceAccess<'a> {
+    fn resource(&self) -> &dyn Reflection {
+        match self {
+            ResourceAccess::Read(resource) => *resource,
+            ResourceAccess::Write(resource) => *resource,
+        }
+    }
+
+    fn is_writable(&self) -> bool {
+        match self {
+            ResourceAccess::Read(_) => false,
+            ResourceAccess::Write(_) => true,
+        }
+    }
+}
+
+/// Fields of a resource, read and written from Lua through its
+/// `Reflection` implementation.
+///
+/// `pairs` lists the fields along with their current values.
+struct ResourceProxy<'a> {
+    name: String,
+    access: ResourceAccess<'a>,
+}
+
+impl<'a> UserData for ResourceProxy<'a> {
+    fn add_methods<'lua, T: UserDataMethods<'lua, Self>>(methods: &mut T) {
+        methods.add_meta_method(MetaMethod::Index, |lua_ctx, proxy, field: String| {
+            proxy.access.resource().get_field(lua_ctx, &field)
+        });
+
+        methods.add_meta_method(MetaMethod::Pairs, |lua_ctx, proxy, ()| {
+            let resource = proxy.access.resource();
+            let fields = lua_ctx.create_table()?;
+            for field in resource.fields() {
+                fields.raw_set(field.name, resource.get_field(lua_ctx, field.name)?)?;
+            }
+
+            let next: Function = lua_ctx.globals().raw_get("next")?;
+            Ok((next, fields, Value::Nil))
+        });
+
+        methods.add_meta_method_mut(
+            MetaMethod::NewIndex,
+            |lua_ctx, proxy, (field, value): (String, Value)| match &mut proxy.access {
+                ResourceAccess::Read(_) => Err(rlua::Error::RuntimeError(format!(
+                    "can't set '{}' of resource '{}', which is read-only",
+                    field, proxy.name
+                ))),
+                ResourceAccess::Write(resource) => resource.set_field(lua_ctx, &field, value),
+            },
+        );
+    }
+}
+
+/// System declared in the `systems` table of a mod.
+///
+/// ```lua
+/// systems = {
+///     count_time = {
+///         reads = { "DeltaTime" },
+///         writes = {},
+///         after = { "othermod:physics" },
+///         before = { "show_time" },
+///         run = function(data) end,
+///     },
+/// }
+/// ```
+///
+/// Names in `after` and `before` refer to systems of the same mod,
+/// unless they are prefixed with the name of another mod. The `run`
+/// function is looked up again every time the system runs, so a
+/// reloaded mod runs its new version.
+pub struct ScriptSystemDecl {
+    /// Name of the system, prefixed with the name of its mod.
+    pub name: String,
+    /// Mod that declared the system.
+    pub mod_name: String,
+    pub reads: Vec<String>,
+    pub writes: Vec<String>,
+    /// Systems that must run before this one.
+    pub after: Vec<String>,
+    /// Systems that must run after this one.
+    pub before: Vec<String>,
+}
+
+impl ScriptSystemDecl {
+    fn from_table(mod_name: &str, name: &str, table: Table) -> rlua::Result<Self> {
+        let qualify = |names: Option<Vec<String>>| {
+            names
+                .unwrap_or_default()
+                .into_iter()
+                .map(|name| {
+                    if name.contains(':') {
+                        name
+                    } else {
+                        format!("{}:{}", mod_name, name)
+                    }
+                })
+                .collect()
+        };
+
+        table.get::<_, Function>("run")?;
+        Ok(ScriptSystemDecl {
+            name: format!("{}:{}", mod_name, name),
+            mod_name: mod_name.to_owned(),
+            reads: table.get::<_, Option<_>>("reads")?.unwrap_or_default(),
+            writes: table.get::<_, Option<_>>("writes")?.unwrap_or_default(),
+            after: qualify(table.get("after")?),
+            before: qualify(table.get("before")?),
+        })
+    }
+}
+
+/// Reads the systems declared by the enabled mods, in load order.
+///
+/// Returns the systems along with the problems found in declarations
+/// that could not be read.
+pub fn collect_systems(
+    lua_ctx: Context,
+    mod_hub: &ModHub,
+) -> rlua::Result<(Vec<ScriptSystemDecl>, Vec<ModLoadError>)> {
+    let mut systems = Vec::new();
+    let mut errors = Vec::new();
+
+    for m in mod_hub.load_order() {
+        if m.state() != ModState::Enabled {
+            continue;
+        }
+        let environment = match m.environment(lua_ctx)? {
+            Some(environment) => environment,
+            None => continue,
+        };
+        let declared = match environment.raw_get::<_, Option<Table>>("systems") {
+            Ok(Some(declared)) => declared,
+            Ok(None) => continue,
+            Err(err) => {
+                errors.push(ModLoadError::System {
+                    mod_name: m.name().to_owned(),
+                    message: format!("invalid systems: {}", err),
+                });
+                continue;
+            }
+        };
+
+        for pair in declared.pairs::<String, Table>() {
+            let (name, table) = match pair {
+                Ok(pair) => pair,
+                Err(err) => {
+                    errors.push(ModLoadError::System {
+                        mod_name: m.name().to_owned(),
+                        message: format!("invalid systems: {}", err),
+                    });
+                    continue;
+                }
+            };
+            match ScriptSystemDecl::from_table(m.name(), &name, table) {
+                Ok(system) => systems.push(system),
+                Err(err) => errors.push(ModLoadError::System {
+                    mod_name: m.name().to_owned(),
+                    message: format!("invalid system '{}:{}': {}", m.name(), name, err),
+                }),
+            }
+        }
+    }
+
+    Ok((systems, errors))
+}
+
+/// Adds script systems to a dispatcher, so that each runs after the
+/// systems in its `after` list and before those in its `before` list.
+///
+/// Systems with unknown resources, or that must run after systems
+/// which are missing or were not added, are left out, as are systems
+/// that depend on each other in a cycle. The problems are returned,
+/// for the report of the mods.
+pub fn add_systems(
+    builder: &mut DispatcherBuilder,
+    systems: Vec<ScriptSystemDecl>,
+    resources: &ResourceTable,
+    runner: &ModRunner,
+    sender: &Sender<Lua>,
+    receiver: &Receiver<Lua>,
+) -> Vec<ModLoadError> {
+    let mut errors = Vec::new();
+
+    let mut resolved = BTreeMap::new();
+    for system in systems {
+        match (
+            resources.resolve(&system.reads),
+            resources.resolve(&system.writes),
+        ) {
+            (Ok(mut reads), Ok(writes)) => {
+                // Resources that are written can be read as well, and
+                // can't be borrowed twice.
+                reads.retain(|(_, id)| !writes.iter().any(|(_, written)| written == id));
+                resolved.insert(system.name.clone(), (system, reads, writes));
+            }
+            (Err(resource), _) | (_, Err(resource)) => errors.push(ModLoadError::UnknownResource {
+                mod_name: system.mod_name,
+                system: system.name,
+                resource,
+            }),
+        }
+    }
 
-        methods.add_method_mut("write", |lua_ctx, data, ()| Ok(()));
+    // Systems that must run first, with `before` turned around. A
+    // system that was left out doesn't hold back the systems it was
+    // declared before.
+    let mut dependencies: BTreeMap<String, BTreeSet<String>> = resolved
+        .iter()
+        .map(|(name, (system, _, _))| (name.clone(), system.after.iter().cloned().collect()))
+        .collect();
+    for (name, (system, _, _)) in &resolved {
+        for later in &system.before {
+            match dependencies.get_mut(later) {
+                Some(dependencies) => {
+                    dependencies.insert(name.clone());
+                }
+                None => errors.push(ModLoadError::System {
+                    mod_name: system.mod_name.clone(),
+                    message: format!("system '{}' runs before unknown system '{}'", name, later),
+                }),
+            }
+        }
+    }
+
+    // Leave out systems whose dependencies are missing, until none are.
+    while let Some((name, dependency)) = dependencies.iter().find_map(|(name, after)| {
+        after
+            .iter()
+            .find(|dependency| !dependencies.contains_key(*dependency))
+            .map(|dependency| (name.clone(), dependency.clone()))
+    }) {
+        let system = &resolved[&name].0;
+        if system.after.contains(&dependency) {
+            errors.push(ModLoadError::System {
+                mod_name: system.mod_name.clone(),
+                message: format!(
+                    "failed adding system '{}': it runs after '{}', which is missing",
+                    name, dependency
+                ),
+            });
+            dependencies.remove(&name);
+        } else if let Some(after) = dependencies.get_mut(&name) {
+            after.remove(&dependency);
+        }
+    }
+
+    // Systems are added after their dependencies, as the builder
+    // requires, taking the first ready system by name to keep the
+    // order stable.
+    let mut added = BTreeSet::new();
+    while let Some(name) = dependencies
+        .iter()
+        .find(|(_, after)| after.iter().all(|dependency| added.contains(dependency)))
+        .map(|(name, _)| name.clone())
+    {
+        let after = dependencies.remove(&name).unwrap_or_default();
+        let (system, reads, writes) = resolved.remove(&name).expect("system was resolved");
+        let after: Vec<&str> = after.iter().map(String::as_str).collect();
+
+        builder.add(
+            ScriptSystem::new(
+                name.clone(),
+                system.mod_name,
+                runner.clone(),
+                sender.clone(),
+                receiver.clone(),
+                &reads,
+                &writes,
+            ),
+            &name,
+            &after,
+        );
+        added.insert(name);
+    }
+
+    if !dependencies.is_empty() {
+        let names: Vec<&str> = dependencies.keys().map(String::as_str).collect();
+        let mod_names: BTreeSet<&str> = dependencies
+            .keys()
+            .map(|name| resolved[name].0.mod_name.as_str())
+            .collect();
+        for mod_name in mod_names {
+            errors.push(ModLoadError::System {
+                mod_name: mod_name.to_owned(),
+                message: format!(
+                    "failed adding systems '{}': they are part of, or run after, a cycle",
+                    names.join("', '")
+                ),
+            });
+        }
+    }
+
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Reflection)]
+    struct Score {
+        value: i32,
+        label: String,
+        #[reflection(skip)]
+        history: Vec<i32>,
+    }
+
+    fn score() -> Score {
+        Score {
+            value: 3,
+            label: "best".to_owned(),
+            history: vec![1, 2],
+        }
+    }
+
+    #[test]
+    fn derived_reflection_lists_fields() {
+        let names: Vec<_> = score()
+            .fields()
+            .iter()
+            .map(|field| (field.name, field.type_name))
+            .collect();
+
+        assert_eq!(names, vec![("value", "i32"), ("label", "String")]);
+    }
+
+    #[test]
+    fn derived_reflection_round_trips_fields() {
+        let lua = Lua::new();
+        let mut score = score();
+
+        lua.context(|lua_ctx| {
+            assert_eq!(
+                lua_ctx
+                    .unpack::<i32>(score.get_field(lua_ctx, "value").unwrap())
+                    .unwrap(),
+                3
+            );
+            assert!(matches!(
+                score.get_field(lua_ctx, "history").unwrap(),
+                Value::Nil
+            ));
+
+            score
+                .set_field(lua_ctx, "value", Value::Integer(7))
+                .unwrap();
+            let label = lua_ctx.pack("worst").unwrap();
+            score.set_field(lua_ctx, "label", label).unwrap();
+            assert_eq!(
+                lua_ctx
+                    .unpack::<i32>(score.get_field(lua_ctx, "value").unwrap())
+                    .unwrap(),
+                7
+            );
+            assert_eq!(
+                lua_ctx
+                    .unpack::<String>(score.get_field(lua_ctx, "label").unwrap())
+                    .unwrap(),
+                "worst"
+            );
+
+            assert!(score.set_field(lua_ctx, "missing", Value::Nil).is_err());
+            assert!(score
+                .set_field(lua_ctx, "value", Value::Boolean(true))
+                .is_err());
+        });
+        assert_eq!(score.value, 7);
+        assert_eq!(score.history, vec![1, 2]);
+    }
+
+    #[test]
+    fn resource_proxy_pairs_lists_fields() {
+        let lua = Lua::new();
+        let score = score();
+
+        let value: i32 = lua
+            .context(|lua_ctx| {
+                lua_ctx.scope(|scope| {
+                    let proxy = scope.create_nonstatic_userdata(ResourceProxy {
+                        name: "Score".to_owned(),
+                        access: ResourceAccess::Read(&score),
+                    })?;
+                    lua_ctx.globals().set("score", proxy)?;
+
+                    lua_ctx
+                        .load(
+                            r#"
+                        local fields = {}
+                        for name, value in pairs(score) do
+                            fields[name] = value
+                        end
+                        assert(fields.label == "best")
+                        return fields.value
+                        "#,
+                        )
+                        .eval()
+                })
+            })
+            .unwrap();
+
+        assert_eq!(value, 3);
+    }
+
+    #[test]
+    fn unknown_resources_are_reported() {
+        use crate::modding::{BudgetMeter, NameRegistry};
+        use crossbeam::channel::bounded;
+        use std::sync::{Arc, Mutex};
+
+        let mut resources = ResourceTable::new();
+        resources.register::<crate::delta_time::DeltaTime>("DeltaTime");
+        let runner = ModRunner::new(
+            BudgetMeter::new(u64::MAX, usize::MAX, 1000),
+            Arc::new(Mutex::new(NameRegistry::new())),
+            BTreeMap::new(),
+        );
+        let (sender, receiver) = bounded(1);
+        let system = |name: &str, reads: &[&str]| ScriptSystemDecl {
+            name: format!("example:{}", name),
+            mod_name: "example".to_owned(),
+            reads: reads.iter().map(|name| (*name).to_owned()).collect(),
+            writes: Vec::new(),
+            after: Vec::new(),
+            before: Vec::new(),
+        };
+
+        let mut builder = DispatcherBuilder::new();
+        let errors = add_systems(
+            &mut builder,
+            vec![
+                system("clock", &["DeltaTime"]),
+                system("score", &["DeltaTime", "Score"]),
+            ],
+            &resources,
+            &runner,
+            &sender,
+            &receiver,
+        );
+
+        match errors.as_slice() {
+            [ModLoadError::UnknownResource {
+                mod_name,
+                system,
+                resource,
+            }] => {
+                assert_eq!(mod_name, "example");
+                assert_eq!(system, "example:score");
+                assert_eq!(resource, "Score");
+            }
+            errors => panic!("expected an unknown resource, got {:?}", errors),
+        }
     }
 }